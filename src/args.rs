@@ -1,12 +1,22 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 #[clap(version, author, about)]
 pub struct Args {
-    /// Config file
-    #[arg(short, long = "config", default_value = "picodocs.yml")]
-    pub config_path: PathBuf,
+    /// Config file. Defaults to picodocs.yml, picodocs.yaml or picodocs.toml in the current
+    /// directory or the closest parent directory with one
+    #[arg(short, long = "config", global = true)]
+    pub config_path: Option<PathBuf>,
+
+    /// Override a configuration key, e.g. `--set base_url=https://staging.example.com/`. Takes
+    /// precedence over `PICODOCS_*` environment variables and the config file. May be repeated
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    pub overrides: Vec<String>,
+
+    /// Maximum number of threads used for building. Defaults to the number of CPU cores
+    #[arg(short, long, global = true)]
+    pub jobs: Option<usize>,
 
     #[command(subcommand)]
     pub command: Command,
@@ -19,6 +29,10 @@ pub enum Command {
         /// Where to place rendered site files
         #[arg(short, long)]
         output_dir: Option<PathBuf>,
+
+        /// Also publish draft pages
+        #[arg(long)]
+        drafts: bool,
     },
 
     /// Preview the site with a live-reloading server
@@ -30,6 +44,58 @@ pub enum Command {
         /// Launch site in default browser
         #[arg(long, short)]
         open: bool,
+
+        /// If the port of `address` is taken, try the following ports
+        #[arg(long)]
+        next_free_port: bool,
+
+        /// Serve the output directory produced by `build` as is, without live reload, to check
+        /// exactly what will be deployed
+        #[arg(long = "static")]
+        static_output: bool,
+    },
+
+    /// Create a new documentation project
+    Init {
+        /// Directory to create the project in
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Format of the configuration file
+        #[arg(long, value_enum, default_value_t = ConfigFormat::Yaml)]
+        format: ConfigFormat,
+
+        /// Name of the site. Asked for if not given
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Add the page template to `templates/`, to customize it
+        #[arg(long)]
+        templates: bool,
+
+        /// Don't add a GitHub Actions workflow publishing the site to GitHub Pages
+        #[arg(long)]
+        no_workflow: bool,
+
+        /// Don't ask any questions, using the defaults and the given flags
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Overwrite files if already existing
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Create a configuration from that of another documentation tool
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
     },
 
     /// Dump the default configuration to a file
@@ -43,9 +109,93 @@ pub enum Command {
         force: bool,
     },
 
+    /// Generate reference documentation
+    Reference {
+        #[command(subcommand)]
+        kind: ReferenceKind,
+    },
+
     /// Generate shell completions
     Completion {
         /// Which shell to generate completions for
         shell: clap_complete::Shell,
     },
 }
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Print the effective configuration, with where each value comes from
+    Show,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ImportSource {
+    /// Translate the configuration of a MkDocs project, reporting what can't be carried over
+    Mkdocs {
+        /// The MkDocs configuration file
+        #[arg(default_value = "mkdocs.yml")]
+        mkdocs_config: PathBuf,
+
+        /// Where to write the configuration. Defaults to picodocs.yml next to the MkDocs
+        /// configuration
+        #[arg(short, long)]
+        output_path: Option<PathBuf>,
+
+        /// Overwrite configuration if already existing
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Translate the configuration of an mdBook book, with the navigation from its SUMMARY.md
+    Mdbook {
+        /// The mdBook configuration file
+        #[arg(default_value = "book.toml")]
+        book_config: PathBuf,
+
+        /// Where to write the configuration. Defaults to picodocs.yml next to book.toml
+        #[arg(short, long)]
+        output_path: Option<PathBuf>,
+
+        /// Read the navigation from SUMMARY.md on every build with `nav_source`, instead of
+        /// converting it once
+        #[arg(long)]
+        nav_source: bool,
+
+        /// Overwrite configuration if already existing
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReferenceKind {
+    /// Markdown reference of the command-line interface
+    Cli {
+        /// Where to write the reference. Defaults to stdout
+        #[arg(short, long)]
+        output_path: Option<PathBuf>,
+    },
+
+    /// Reference of all configuration keys
+    Config {
+        #[arg(short, long, value_enum, default_value_t = ConfigReferenceFormat::Markdown)]
+        format: ConfigReferenceFormat,
+
+        /// Where to write the reference. Defaults to stdout
+        #[arg(short, long)]
+        output_path: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ConfigReferenceFormat {
+    Markdown,
+    /// For validation and autocompletion in editors
+    JsonSchema,
+}
@@ -1,16 +1,58 @@
 use anyhow::Context;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use pulldown_cmark::Options;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::path::PathBuf;
+use std::sync::RwLock;
 use tera::Tera;
 use walkdir::WalkDir;
 
-use crate::config::Conf;
+use crate::cache::RenderCache;
+use crate::config::{Conf, NavItem};
+use crate::exclude::{Exclusions, IGNORE_FILES};
+use crate::search::{self, SearchEntry};
+use crate::{includes, shortcodes};
 
-static TERA: Lazy<Tera> =
-    Lazy::new(|| Tera::new("templates/*.html").expect("Failed to load templates"));
+static TERA: OnceCell<RwLock<Tera>> = OnceCell::new();
+
+fn templates() -> Result<&'static RwLock<Tera>> {
+    TERA.get_or_try_init(|| load_templates().map(RwLock::new))
+}
+
+/// Page templates that are always available. A template with the same name in `templates/` takes
+/// precedence.
+pub const BUILTIN_TEMPLATES: &[(&str, &str)] =
+    &[("base.html", include_str!("../templates/base.html"))];
+
+fn load_templates() -> Result<Tera> {
+    let mut tera = Tera::new("templates/*.html").context("Failed to load templates")?;
+    let mut builtins = Tera::default();
+    builtins
+        .add_raw_templates(BUILTIN_TEMPLATES.iter().copied())
+        .context("Failed to load built-in templates")?;
+    tera.extend(&builtins)
+        .context("Failed to merge built-in templates")?;
+    Ok(tera)
+}
+
+/// Load the page templates and shortcodes again, after they have been edited.
+pub fn reload_templates() -> Result<()> {
+    let tera = load_templates()?;
+    match TERA.get() {
+        Some(templates) => *templates.write().unwrap() = tera,
+        None => {
+            // a concurrent first use loaded them just now, which is as recent
+            let _ = TERA.set(RwLock::new(tera));
+        }
+    }
+    shortcodes::reload()
+}
+
+/// Url path of the page rendered from `404.md`, which static file servers generally show for
+/// missing pages
+pub const NOT_FOUND_PATH: &str = "404.html";
 
 /// A built HTML file, ready to be dumped into the output directory or served
 #[derive(Clone)]
@@ -18,21 +60,53 @@ pub struct Page {
     pub rendered: String,
     pub url_path: String,
     pub front_matter: Option<FrontMatter>,
+    /// Files pulled in by include directives, which the page should be rebuilt on changes to
+    pub included_files: Vec<PathBuf>,
 }
 
 /// A static file (non-markdown) to be served or copied
 pub struct StaticAsset {
     pub url_path: String,
-    pub content: Vec<u8>,
+    pub content: StaticContent,
     pub mime_type: mime_guess::Mime,
 }
 
+/// Content of a static asset
+pub enum StaticContent {
+    /// Generated by picodocs, e.g. the search index
+    Generated(Vec<u8>),
+    /// A file which is published as is, and thus never read into memory
+    File(PathBuf),
+}
+
 #[derive(Debug)]
 pub enum InMemoryAsset {
     Page(Page),
     Static(StaticAsset),
 }
 
+impl Page {
+    pub fn is_draft(&self) -> bool {
+        self.front_matter.as_ref().is_some_and(|fm| fm.draft)
+    }
+
+    /// Whether the page is left out of the navigation and search index. Always true for the
+    /// page shown for missing pages.
+    pub fn is_hidden(&self) -> bool {
+        self.front_matter.as_ref().is_some_and(|fm| fm.hidden) || self.url_path == NOT_FOUND_PATH
+    }
+
+    /// Where the page is placed in the output directory. Pages are generally placed in a
+    /// directory of their own as `index.html`, for clean URLs.
+    pub fn output_path(&self) -> PathBuf {
+        if self.url_path.ends_with(".html") {
+            PathBuf::from(&self.url_path)
+        } else {
+            Path::new(&self.url_path).join("index.html")
+        }
+    }
+}
+
 impl fmt::Debug for Page {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Page")
@@ -46,14 +120,20 @@ impl fmt::Debug for StaticAsset {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("StaticAsset")
             .field("url_path", &self.url_path)
-            .field("content", &format!("<{} bytes>", self.content.len()))
+            .field(
+                "content",
+                &match &self.content {
+                    StaticContent::Generated(content) => format!("<{} bytes>", content.len()),
+                    StaticContent::File(path) => format!("<{path:?}>"),
+                },
+            )
             .field("mime_type", &self.mime_type)
             .finish()
     }
 }
 
 use anyhow::Result;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::path::Path;
 
@@ -61,6 +141,8 @@ use std::path::Path;
 pub struct SitemapNode {
     pub title: String,
     pub path: Option<String>,
+    /// Link to somewhere outside of the site, instead of `path`
+    pub url: Option<String>,
     pub children: Vec<SitemapNode>,
 }
 
@@ -141,6 +223,7 @@ impl SitemapNode {
                     };
 
                     Some(SitemapNode {
+                        url: None,
                         title: current_segment_path
                             .file_stem()
                             .unwrap_or_else(|| OsStr::new(""))
@@ -160,9 +243,83 @@ impl SitemapNode {
         SitemapNode {
             title: "".to_string(),
             path: None,
+            url: None,
             children: build(Path::new(""), pages),
         }
     }
+
+    /// The sitemap laid out by the `nav` setting. Pages that aren't among `pages`, like drafts
+    /// and hidden pages, are left out, along with sections left empty by that.
+    pub fn from_nav(nav: &[NavItem], pages: &[Page]) -> Self {
+        fn page(title: Option<&str>, target: &str, pages: &[Page]) -> Option<SitemapNode> {
+            let rel = Path::new(target.trim_start_matches("./"));
+            let url_path = page_url_path(rel);
+            let page = pages.iter().find(|page| page.url_path == url_path)?;
+            let title = title
+                .map(str::to_string)
+                .or_else(|| page.front_matter.as_ref()?.title.clone())
+                .or_else(|| url_path.rsplit('/').next().map(str::to_string))
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| rel.file_stem().unwrap_or_default().to_string_lossy().into());
+            Some(SitemapNode {
+                title,
+                path: Some(url_path),
+                url: None,
+                children: Vec::new(),
+            })
+        }
+
+        fn build(items: &[NavItem], pages: &[Page]) -> Vec<SitemapNode> {
+            let mut nodes = Vec::new();
+            for item in items {
+                match item {
+                    NavItem::Path(path) => nodes.extend(page(None, path, pages)),
+                    NavItem::ExternalLink(links) => {
+                        for (title, target) in links {
+                            if target.ends_with(".md") {
+                                nodes.extend(page(Some(title), target, pages));
+                            } else {
+                                nodes.push(SitemapNode {
+                                    title: title.clone(),
+                                    path: None,
+                                    url: Some(target.clone()),
+                                    children: Vec::new(),
+                                });
+                            }
+                        }
+                    }
+                    // a section led by a page, like a chapter with sub-chapters, links to it
+                    NavItem::Section(sections) => {
+                        for (title, items) in sections {
+                            let (path, items) = match items.split_first() {
+                                Some((NavItem::Path(path), rest)) => {
+                                    (page(None, path, pages).and_then(|node| node.path), rest)
+                                }
+                                _ => (None, items.as_slice()),
+                            };
+                            let children = build(items, pages);
+                            if path.is_some() || !children.is_empty() {
+                                nodes.push(SitemapNode {
+                                    title: title.clone(),
+                                    path,
+                                    url: None,
+                                    children,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+            nodes
+        }
+
+        SitemapNode {
+            title: "".to_string(),
+            path: None,
+            url: None,
+            children: build(nav, pages),
+        }
+    }
 }
 
 pub enum Asset {
@@ -179,64 +336,171 @@ static MARKDOWN_OPTIONS: Lazy<Options> = Lazy::new(|| {
     options
 });
 
+/// Splits off the front matter: a YAML mapping between two `---` lines, the first of which is the
+/// first line of the file. Anything else, like a leading horizontal rule, is plain markdown.
 pub fn extract_front_matter(md: &str) -> Result<(Option<FrontMatter>, &str)> {
     const DELIMITER: &str = "---";
 
-    if !md.starts_with(DELIMITER) {
+    let is_delimiter = |line: &str| line.trim_end() == DELIMITER;
+
+    let Some((first_line, content)) = md.split_once('\n') else {
+        return Ok((None, md));
+    };
+    if !is_delimiter(first_line) {
         return Ok((None, md));
     }
 
-    let content = &md[DELIMITER.len()..];
-    let end = content
-        .find(DELIMITER)
-        .context("failed to find end of front matter")?;
+    let mut end = 0;
+    let rest = loop {
+        let Some(line) = content[end..].split_inclusive('\n').next() else {
+            return Ok((None, md));
+        };
+        if is_delimiter(line) {
+            break &content[end + line.len()..];
+        }
+        end += line.len();
+    };
 
-    let fm: FrontMatter =
-        serde_yaml::from_str(&content[..end]).context("failed to parse front matter")?;
+    // starting at the opening `---`, which YAML reads as the start of a document, keeps the line
+    // numbers of YAML errors those of the file
+    let yaml = &md[..md.len() - content.len() + end];
 
-    let rest = &content[end + DELIMITER.len()..];
+    // a paragraph between two horizontal rules is valid YAML as well, but not a mapping
+    let value: serde_yaml::Value = serde_yaml::from_str(yaml).context("Invalid front matter")?;
+    if value.is_null() {
+        return Ok((None, rest));
+    }
+    if !value.is_mapping() {
+        return Ok((None, md));
+    }
+    let fm: FrontMatter = serde_yaml::from_str(yaml).context("Invalid front matter")?;
 
     Ok((Some(fm), rest))
 }
 
-fn render_single_markdown_page(md: &str) -> (String, Option<FrontMatter>) {
-    use pulldown_cmark::{CowStr, Event, HeadingLevel, Parser, Tag, html};
+/// Url path of the page rendered from the markdown file `rel`, relative to `docs_dir`.
+fn page_url_path(rel: &Path) -> String {
+    if rel == Path::new("404.md") {
+        return NOT_FOUND_PATH.to_string();
+    }
+    let mut p = rel.to_path_buf();
+    if rel.file_name() == Some(OsStr::new("index.md")) {
+        p.pop();
+    } else {
+        p.set_extension("");
+    }
+    p.to_string_lossy().into_owned()
+}
+
+fn render_single_markdown_page(
+    md: &str,
+    source: &Path,
+    included_files: &mut Vec<PathBuf>,
+    cache: Option<&RenderCache>,
+) -> Result<(RenderedMarkdown, Option<FrontMatter>)> {
+    let (front_matter, rest) = extract_front_matter(md)?;
 
-    let (front_matter, rest) = extract_front_matter(md).unwrap_or((None, md));
+    let first_line = 1 + md[..md.len() - rest.len()].matches('\n').count();
+    let expanded = includes::expand(rest, source, first_line, included_files)?;
+    let line_of = |index| expanded.source_line(index);
 
-    let mut previous_heading_level: Option<HeadingLevel> = None;
-    let parser = Parser::new_ext(rest, *MARKDOWN_OPTIONS).filter_map(|event| match event {
-        Event::Start(Tag::Heading { level, .. }) => {
-            previous_heading_level = Some(level);
-            None
+    let rendered = match cache {
+        Some(cache) => {
+            cache.get_or_render(&expanded.markdown, |md| render_markdown(md, &line_of))?
         }
-        Event::Text(text) => {
-            let Some(heading_level) = previous_heading_level.take() else {
-                return Some(Event::Text(text));
-            };
+        None => render_markdown(&expanded.markdown, &line_of)?,
+    };
 
-            let anchor: String = text
-                .to_lowercase()
-                .chars()
-                .map(|c| if c.is_alphanumeric() { c } else { '-' })
-                .collect();
+    Ok((rendered, front_matter))
+}
+
+/// Markdown rendered to HTML, along with the plain text needed for the search index
+#[derive(Serialize, Deserialize)]
+struct RenderedMarkdown {
+    html: String,
+    headings: Vec<search::Heading>,
+    text: String,
+}
+
+/// Render markdown (without front matter) to HTML, expanding shortcodes. `line_of` gives the line
+/// of the source file that a line of `md` comes from, by index.
+fn render_markdown(md: &str, line_of: &dyn Fn(usize) -> Option<usize>) -> Result<RenderedMarkdown> {
+    use pulldown_cmark::{CowStr, Event, Parser, Tag, TagEnd, html};
+
+    let shortcodes = shortcodes::expand(md, line_of, &|body, first_line_index| {
+        Ok(render_markdown(body, &|index| line_of(first_line_index + index))?.html)
+    })?;
+
+    let mut events: Vec<Event> = Parser::new_ext(&shortcodes.markdown, *MARKDOWN_OPTIONS).collect();
+
+    let mut headings = Vec::new();
+    let mut text = String::new();
+    let mut in_heading = false;
+
+    // give each heading an anchor derived from its text, and collect the text outside of headings
+    for i in 0..events.len() {
+        match &events[i] {
+            Event::Start(Tag::Heading { .. }) => in_heading = true,
+            Event::End(TagEnd::Heading(_)) => {
+                in_heading = false;
+                continue;
+            }
+            Event::Text(t) | Event::Code(t) if !in_heading => {
+                text.push_str(t);
+                continue;
+            }
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Item
+                | TagEnd::TableCell
+                | TagEnd::CodeBlock
+                | TagEnd::BlockQuote(_),
+            ) => {
+                text.push(' ');
+                continue;
+            }
+            _ => continue,
+        }
+
+        let heading_text = shortcodes::strip_placeholders(
+            &events[i + 1..]
+                .iter()
+                .take_while(|event| !matches!(event, Event::End(TagEnd::Heading(_))))
+                .filter_map(|event| match event {
+                    Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                    _ => None,
+                })
+                .collect::<String>(),
+        );
 
-            let heading_start_and_text = Event::InlineHtml(CowStr::from(format!(
-                "<h{} id=\"{}\">{}",
-                heading_level as u8, anchor, text,
-            )));
+        let anchor: String = heading_text
+            .trim()
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect();
 
-            previous_heading_level = None;
-            Some(heading_start_and_text)
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[i] {
+            *id = Some(CowStr::from(anchor.clone()));
         }
-        _ => Some(event),
-    });
+
+        headings.push(search::Heading {
+            anchor,
+            text: search::normalize_whitespace(&heading_text),
+        });
+    }
 
     // reasonable guess for HTML size?
     let mut html = String::with_capacity((md.len() * 3) / 2);
-    html::push_html(&mut html, parser);
+    html::push_html(&mut html, events.into_iter());
 
-    (html, front_matter)
+    Ok(RenderedMarkdown {
+        html: shortcodes.restore(html),
+        headings,
+        text: search::normalize_whitespace(&shortcodes::strip_placeholders(&text)),
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -244,93 +508,532 @@ pub struct FrontMatter {
     title: Option<String>,
     description: Option<String>,
     keywords: Option<Vec<String>>,
+    /// Only published by `serve`, or when `drafts` is enabled
+    #[serde(default)]
+    draft: bool,
+    /// Rendered, but left out of the navigation and search index
+    #[serde(default)]
+    hidden: bool,
+}
+
+/// A version of the current page in another language, for the language switcher
+#[derive(Debug, Serialize)]
+struct Alternate {
+    code: String,
+    name: String,
+    /// Relative to the root of the site
+    url: String,
+    /// Whether this is an actual translation, rather than falling back to the default language
+    translated: bool,
+    current: bool,
+}
+
+/// Determine the language of the markdown file at `rel`, from either a language directory
+/// (`sv/page.md`) or suffix (`page.sv.md`). Returns the language, and `rel` without it.
+fn split_language(rel: &Path, config: &Conf) -> (String, PathBuf) {
+    let mut components = rel.components();
+    if let Some(first) = components.next().and_then(|c| c.as_os_str().to_str())
+        && config.languages.contains_key(first)
+        && components.clone().next().is_some()
+    {
+        return (first.to_string(), components.as_path().to_path_buf());
+    }
+
+    if let Some(stem) = rel.file_stem().and_then(OsStr::to_str)
+        && let Some((name, language)) = stem.rsplit_once('.')
+        && config.languages.contains_key(language)
+    {
+        return (
+            language.to_string(),
+            rel.with_file_name(format!("{name}.md")),
+        );
+    }
+
+    (config.language.clone(), rel.to_path_buf())
+}
+
+/// Join url paths, without introducing leading or doubled slashes.
+fn join_url_path(prefix: &str, path: &str) -> String {
+    match (prefix.is_empty(), path.trim_start_matches('/')) {
+        (true, path) => path.to_string(),
+        (false, "") if path.is_empty() => prefix.to_string(),
+        (false, path) => format!("{prefix}/{path}"),
+    }
+}
+
+/// An entry in the version selector
+#[derive(Debug, Serialize)]
+struct VersionLink<'a> {
+    name: &'a str,
+    title: &'a str,
+    url: String,
+    current: bool,
 }
 
 /// Read all files from `conf.docs_dir`, return generated assets.
-pub fn get_all_assets(config: &Conf) -> Result<Vec<Asset>> {
-    let file_relative_paths: Vec<PathBuf> = WalkDir::new(&config.docs_dir)
-        .follow_links(config.follow_links)
-        .into_iter()
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.file_type().is_file())
-        .filter_map(|entry| {
-            entry
-                .path()
-                .strip_prefix(&config.docs_dir)
-                .ok()
-                .map(PathBuf::from)
-        })
-        .collect();
+///
+/// `current_version` is the entry of `conf.versions` being built, if any.
+pub fn get_all_assets(config: &Conf, current_version: Option<&str>) -> Result<Vec<Asset>> {
+    Site::load(config)?.assets(config, current_version, None)
+}
 
-    let mut all_assets = Vec::with_capacity(file_relative_paths.len());
+/// A markdown file rendered to HTML, before being placed into the page template
+#[derive(Debug)]
+struct Source {
+    language: String,
+    page: Page,
+    search_entry: Option<SearchEntry>,
+}
 
-    let (page_relative_paths, static_relative_paths): (Vec<_>, Vec<_>) = file_relative_paths
-        .into_iter()
-        .partition(|rel| rel.extension() == Some(OsStr::new("md")));
+/// All files of a site, with markdown rendered but not yet placed into the page template.
+///
+/// Kept around by `serve`, so that a change only renders the files affected by it again.
+pub struct Site {
+    /// Markdown files, by path relative to `docs_dir`
+    sources: BTreeMap<PathBuf, Source>,
+    /// Static files, relative to `docs_dir`
+    static_files: BTreeSet<PathBuf>,
+}
+
+/// Assets affected by [`Site::update`]
+pub enum AssetChanges {
+    /// Every asset of the site, replacing all previous ones
+    All(Vec<Asset>),
+    /// Assets to add or replace, and the url paths of assets which no longer exist
+    Some {
+        updated: Vec<Asset>,
+        removed: Vec<String>,
+    },
+}
 
-    let pages: Vec<Page> = page_relative_paths
-        .into_iter()
-        .map(|rel| {
-            let md = std::fs::read_to_string(config.docs_dir.join(&rel))
-                .with_context(|| format!("Failed to read markdown file {rel:?}"))?;
+impl Site {
+    /// Read and render all files in `config.docs_dir`.
+    pub fn load(config: &Conf) -> Result<Self> {
+        if !config.docs_dir.is_dir() {
+            anyhow::bail!("docs_dir {:?} does not exist", config.docs_dir);
+        }
 
-            let (html, front_matter) = render_single_markdown_page(&md);
+        let exclusions = Exclusions::new(config)?;
+        let cache = open_cache(config)?;
 
-            let current_path = {
-                let mut p = rel.clone();
-                if rel.file_name() == Some(OsStr::new("index.md")) {
-                    p.pop();
-                } else {
-                    p.set_extension("");
+        let file_relative_paths: Vec<PathBuf> = WalkDir::new(&config.docs_dir)
+            .follow_links(config.follow_links)
+            .into_iter()
+            .filter_entry(|entry| {
+                entry
+                    .path()
+                    .strip_prefix(&config.docs_dir)
+                    .is_ok_and(|rel| !exclusions.is_excluded(rel, entry.file_type().is_dir()))
+            })
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                entry
+                    .path()
+                    .strip_prefix(&config.docs_dir)
+                    .ok()
+                    .map(PathBuf::from)
+            })
+            .collect();
+
+        let (page_relative_paths, static_files): (Vec<PathBuf>, Vec<PathBuf>) = file_relative_paths
+            .into_iter()
+            .partition(|rel| is_markdown(rel));
+
+        let sources = page_relative_paths
+            .into_par_iter()
+            .map(|rel| {
+                let source = render_source(config, &rel, cache.as_ref())?;
+                Ok((rel, source))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Site {
+            sources,
+            static_files: static_files.into_iter().collect(),
+        })
+    }
+
+    /// Render the files at `changed_paths` again, along with pages including them. Returns the
+    /// assets affected, which is all of them if the navigation changed.
+    pub fn update(
+        &mut self,
+        config: &Conf,
+        exclusions: &Exclusions,
+        changed_paths: &[PathBuf],
+    ) -> Result<AssetChanges> {
+        let docs_dir = std::path::absolute(&config.docs_dir)
+            .with_context(|| format!("Failed to resolve {:?}", config.docs_dir))?;
+
+        let mut changed_sources = BTreeSet::new();
+        let mut changed_static_files = BTreeSet::new();
+
+        for path in changed_paths {
+            let path =
+                std::path::absolute(path).with_context(|| format!("Failed to resolve {path:?}"))?;
+
+            for (rel, source) in &self.sources {
+                if source
+                    .page
+                    .included_files
+                    .iter()
+                    .any(|included| std::path::absolute(included).is_ok_and(|p| p == path))
+                {
+                    changed_sources.insert(rel.clone());
                 }
-                p.to_str().unwrap().to_string()
+            }
+
+            let Ok(rel) = path.strip_prefix(&docs_dir) else {
+                continue;
             };
 
-            Ok(Page {
-                rendered: html,
-                url_path: current_path,
-                front_matter,
+            // changes to directories or ignore files may affect any number of files
+            let removed_dir = !path.exists()
+                && self
+                    .sources
+                    .keys()
+                    .chain(&self.static_files)
+                    .any(|known| known.starts_with(rel) && known != rel);
+            let ignore_file = rel
+                .file_name()
+                .is_some_and(|name| IGNORE_FILES.iter().any(|f| name == *f));
+            if path.is_dir() || removed_dir || ignore_file {
+                *self = Site::load(config)?;
+                return Ok(AssetChanges::All(self.assets(config, None, None)?));
+            }
+
+            if is_markdown(rel) {
+                changed_sources.insert(rel.to_path_buf());
+            } else {
+                changed_static_files.insert(rel.to_path_buf());
+            }
+        }
+
+        let cache = open_cache(config)?;
+        let exists =
+            |rel: &Path| config.docs_dir.join(rel).is_file() && !exclusions.is_excluded(rel, false);
+
+        // render everything before changing anything, so a failure leaves the site as it was
+        let rendered: Vec<(PathBuf, Option<Source>)> = changed_sources
+            .into_par_iter()
+            .map(|rel| {
+                let source = exists(&rel)
+                    .then(|| render_source(config, &rel, cache.as_ref()))
+                    .transpose()?;
+                Ok((rel, source))
             })
-        })
-        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+            .collect::<Result<_>>()?;
 
-    let sitemap_root = SitemapNode::new(&pages);
+        let structure_before = self.structure(config);
 
-    for page in pages {
-        let mut ctx = tera::Context::new();
-        ctx.try_insert("config", &config)?;
-        ctx.try_insert("sitemap_root", &sitemap_root)?;
-        ctx.try_insert("current_path", &page.url_path)?;
-        ctx.try_insert("content", &page.rendered)?;
+        let mut rendered_sources = HashSet::new();
+        for (rel, source) in rendered {
+            match source {
+                Some(source) => {
+                    self.sources.insert(rel.clone(), source);
+                    rendered_sources.insert(rel);
+                }
+                None => {
+                    self.sources.remove(&rel);
+                }
+            }
+        }
 
-        if let Some(front_matter) = &page.front_matter {
-            ctx.extend(
-                tera::Context::from_serialize(front_matter)
-                    .with_context(|| format!("Serialize front matter for {:?}", &page.url_path))?,
-            );
+        if self.structure(config) != structure_before {
+            return Ok(AssetChanges::All(self.assets(config, None, None)?));
         }
 
-        let rendered = TERA
-            .render("base.html", &ctx)
-            .with_context(|| format!("Render template for {:?}", &page.url_path))?;
+        let mut updated = self.assets(config, None, Some(&rendered_sources))?;
+        let mut removed = Vec::new();
+        for rel in changed_static_files {
+            if exists(&rel) {
+                updated.push(static_file_asset(config, &rel));
+                self.static_files.insert(rel);
+            } else if self.static_files.remove(&rel) {
+                removed.push(rel.to_string_lossy().into_owned());
+            }
+        }
 
-        all_assets.push(Asset::Page(Page {
-            rendered,
-            url_path: page.url_path,
-            front_matter: page.front_matter,
-        }));
+        Ok(AssetChanges::Some { updated, removed })
     }
 
-    for rel in static_relative_paths {
-        let content = std::fs::read(config.docs_dir.join(&rel))
-            .with_context(|| format!("Read static file {rel:?}"))?;
+    /// Everything about the pages that all pages depend on, i.e. which pages there are in which
+    /// languages, and which of them are in the navigation.
+    fn structure(&self, config: &Conf) -> Vec<(String, String, bool)> {
+        self.sources
+            .values()
+            .filter(|source| config.drafts || !source.page.is_draft())
+            .map(|source| {
+                (
+                    source.language.clone(),
+                    source.page.url_path.clone(),
+                    source.page.is_hidden(),
+                )
+            })
+            .collect()
+    }
+
+    /// Generate the assets of the site.
+    ///
+    /// If `only` is given, only pages rendered from those markdown files are generated, along with
+    /// the search indexes.
+    pub fn assets(
+        &self,
+        config: &Conf,
+        current_version: Option<&str>,
+        only: Option<&HashSet<PathBuf>>,
+    ) -> Result<Vec<Asset>> {
+        let mut all_assets = Vec::with_capacity(self.sources.len() + self.static_files.len());
+
+        let sources: Vec<(&PathBuf, &Source)> = self
+            .sources
+            .iter()
+            .filter(|(_, source)| config.drafts || !source.page.is_draft())
+            .collect();
 
-        all_assets.push(Asset::Static(StaticAsset {
-            content,
-            url_path: rel.to_string_lossy().into_owned(),
-            mime_type: mime_guess::from_path(&rel).first_or_octet_stream(),
-        }));
+        // index into `sources` of each page, by language and path
+        let translations: HashMap<(&str, &str), usize> = sources
+            .iter()
+            .enumerate()
+            .map(|(i, (_, source))| ((source.language.as_str(), source.page.url_path.as_str()), i))
+            .collect();
+
+        let languages = config.all_languages();
+        // where the pages being built are placed, as a path and as a full URL
+        let (site_root, site_url) = {
+            let version_prefix = current_version
+                .map(|version| format!("{version}/"))
+                .unwrap_or_default();
+            (
+                format!("{}{version_prefix}", config.root_path()),
+                format!("{}/{version_prefix}", config.base_url.trim_end_matches('/')),
+            )
+        };
+
+        let versions: Vec<VersionLink> = match current_version {
+            Some(current_version) => config
+                .versions
+                .iter()
+                .map(|version| VersionLink {
+                    name: &version.name,
+                    title: version.title.as_deref().unwrap_or(&version.name),
+                    url: format!("{}{}/", config.root_path(), version.name),
+                    current: version.name == current_version,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        for &language in &languages {
+            let lang_prefix = config.language_prefix(language);
+            let language_config = config.for_language(language);
+            // prepended to paths in the sitemap when linking to them
+            let nav_prefix = if lang_prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{lang_prefix}/")
+            };
+
+            // pages of the default language, replaced by their translation where one exists,
+            // followed by pages only existing in this language
+            let page_indices: Vec<usize> = sources
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (_, source))| {
+                    let url_path = source.page.url_path.as_str();
+                    if source.language == config.language {
+                        Some(
+                            translations
+                                .get(&(language, url_path))
+                                .copied()
+                                .unwrap_or(i),
+                        )
+                    } else if source.language == language
+                        && !translations.contains_key(&(config.language.as_str(), url_path))
+                    {
+                        Some(i)
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            let pages: Vec<Page> = page_indices
+                .iter()
+                .map(|&i| &sources[i].1.page)
+                .filter(|page| !page.is_hidden())
+                .cloned()
+                .collect();
+            let sitemap_root = match &config.nav {
+                Some(nav) => SitemapNode::from_nav(nav, &pages),
+                None => SitemapNode::new(&pages),
+            };
+
+            let rendered_pages: Vec<Page> = page_indices
+                .par_iter()
+                .filter(|&&i| only.is_none_or(|only| only.contains(sources[i].0)))
+                .map(|&i| -> Result<_> {
+                    let Source {
+                        language: page_language,
+                        page,
+                        ..
+                    } = sources[i].1;
+                    let url_path = join_url_path(lang_prefix, &page.url_path);
+
+                    let alternates: Vec<Alternate> = languages
+                        .iter()
+                        .filter_map(|&alternate| {
+                            let translated =
+                                translations.contains_key(&(alternate, page.url_path.as_str()));
+                            let exists = translated
+                                || translations.contains_key(&(
+                                    config.language.as_str(),
+                                    page.url_path.as_str(),
+                                ));
+                            exists.then(|| Alternate {
+                                code: alternate.to_string(),
+                                name: config.language_name(alternate).to_string(),
+                                url: join_url_path(
+                                    config.language_prefix(alternate),
+                                    &page.url_path,
+                                ),
+                                translated,
+                                current: alternate == language,
+                            })
+                        })
+                        .collect();
+
+                    let mut ctx = tera::Context::new();
+                    ctx.try_insert("config", &language_config)?;
+                    ctx.try_insert("sitemap_root", &sitemap_root)?;
+                    ctx.try_insert("site_root", &site_root)?;
+                    ctx.try_insert("site_url", &site_url)?;
+                    ctx.try_insert("versions", &versions)?;
+                    ctx.try_insert("current_path", &page.url_path)?;
+                    ctx.try_insert("lang_prefix", &nav_prefix)?;
+                    ctx.try_insert("page_language", page_language)?;
+                    ctx.try_insert("languages", &alternates)?;
+                    ctx.try_insert("content", &page.rendered)?;
+
+                    if let Some(front_matter) = &page.front_matter {
+                        ctx.extend(tera::Context::from_serialize(front_matter).with_context(
+                            || format!("Serialize front matter for {:?}", &page.url_path),
+                        )?);
+                    }
+
+                    let rendered = templates()?
+                        .read()
+                        .unwrap()
+                        .render("base.html", &ctx)
+                        .with_context(|| format!("Render template for {url_path:?}"))?;
+
+                    Ok(Page {
+                        rendered,
+                        url_path,
+                        front_matter: page.front_matter.clone(),
+                        included_files: page.included_files.clone(),
+                    })
+                })
+                .collect::<Result<_>>()?;
+            all_assets.extend(rendered_pages.into_iter().map(Asset::Page));
+
+            let search_entries: Vec<SearchEntry> = page_indices
+                .iter()
+                .filter_map(|&i| sources[i].1.search_entry.as_ref())
+                .map(|search_entry| SearchEntry {
+                    url_path: join_url_path(lang_prefix, &search_entry.url_path),
+                    ..search_entry.clone()
+                })
+                .collect();
+
+            all_assets.push(Asset::Static(StaticAsset {
+                content: StaticContent::Generated(
+                    serde_json::to_vec(&search_entries).context("Serialize search index")?,
+                ),
+                url_path: join_url_path(lang_prefix, search::INDEX_PATH),
+                mime_type: mime_guess::mime::APPLICATION_JSON,
+            }));
+        }
+
+        if only.is_none() {
+            all_assets.push(Asset::Static(StaticAsset {
+                content: StaticContent::Generated(search::SCRIPT.to_vec()),
+                url_path: search::SCRIPT_PATH.to_string(),
+                mime_type: mime_guess::mime::APPLICATION_JAVASCRIPT,
+            }));
+
+            all_assets.extend(
+                self.static_files
+                    .iter()
+                    .map(|rel| static_file_asset(config, rel)),
+            );
+        }
+
+        Ok(all_assets)
     }
+}
+
+fn is_markdown(rel: &Path) -> bool {
+    rel.extension() == Some(OsStr::new("md"))
+}
+
+fn open_cache(config: &Conf) -> Result<Option<RenderCache>> {
+    config
+        .cache_dir
+        .as_deref()
+        .map(RenderCache::open)
+        .transpose()
+}
+
+fn static_file_asset(config: &Conf, rel: &Path) -> Asset {
+    Asset::Static(StaticAsset {
+        content: StaticContent::File(config.docs_dir.join(rel)),
+        url_path: rel.to_string_lossy().into_owned(),
+        mime_type: mime_guess::from_path(rel).first_or_octet_stream(),
+    })
+}
+
+/// Read and render the markdown file at `rel`, relative to `docs_dir`.
+fn render_source(config: &Conf, rel: &Path, cache: Option<&RenderCache>) -> Result<Source> {
+    let source = config.docs_dir.join(rel);
+    let md = std::fs::read_to_string(&source)
+        .with_context(|| format!("Failed to read markdown file {rel:?}"))?;
+
+    let mut included_files = Vec::new();
+    let (rendered, front_matter) =
+        render_single_markdown_page(&md, &source, &mut included_files, cache)
+            .with_context(|| format!("Failed to render markdown file {rel:?}"))?;
+
+    let (language, rel) = split_language(rel, config);
+
+    let current_path = page_url_path(&rel);
+
+    let hidden =
+        front_matter.as_ref().is_some_and(|fm| fm.hidden) || current_path == NOT_FOUND_PATH;
+    let search_entry = (!hidden && !search::is_excluded(&current_path, &config.search_exclude))
+        .then(|| SearchEntry {
+            url_path: current_path.clone(),
+            title: front_matter
+                .as_ref()
+                .and_then(|fm| fm.title.clone())
+                .or_else(|| rendered.headings.first().map(|h| h.text.clone()))
+                .unwrap_or_else(|| current_path.clone()),
+            keywords: front_matter
+                .as_ref()
+                .and_then(|fm| fm.keywords.clone())
+                .unwrap_or_default(),
+            headings: rendered.headings,
+            text: rendered.text,
+        });
 
-    Ok(all_assets)
+    Ok(Source {
+        language,
+        page: Page {
+            rendered: rendered.html,
+            url_path: current_path,
+            front_matter,
+            included_files,
+        },
+        search_entry,
+    })
 }
@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+/// Cache of rendered markdown between builds, stored as one JSON file per entry in `cache_dir`.
+///
+/// Entries are keyed by a SHA-256 digest of the markdown along with everything else that affects
+/// how it is rendered: the version of picodocs and the shortcode templates.
+pub struct RenderCache {
+    dir: PathBuf,
+    /// Digest of the picodocs version and the shortcode templates
+    global_key: [u8; 32],
+}
+
+/// A cached rendering, along with the digest of the markdown it was rendered from
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    source: String,
+    rendered: T,
+}
+
+/// Hex-encoded SHA-256 digest of each of `parts`, which are length-prefixed so that different
+/// splits of the same bytes don't collide.
+fn digest<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+impl RenderCache {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create cache dir {dir:?}"))?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        for entry in WalkDir::new("shortcodes")
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+        {
+            let content = fs::read(entry.path())
+                .with_context(|| format!("Failed to read shortcode {:?}", entry.path()))?;
+            hasher.update(digest([
+                entry.path().as_os_str().as_encoded_bytes(),
+                content.as_slice(),
+            ]));
+        }
+
+        Ok(RenderCache {
+            dir: dir.to_path_buf(),
+            global_key: hasher.finalize().into(),
+        })
+    }
+
+    fn entry_path(&self, md: &str) -> PathBuf {
+        let key = digest([self.global_key.as_slice(), md.as_bytes()]);
+        self.dir.join(format!("{key}.json"))
+    }
+
+    /// Get the cached rendering of `md`, or render and cache it with `render`.
+    ///
+    /// Entries that are used have their modification time updated, so that [`prune`] can tell
+    /// which are still needed.
+    pub fn get_or_render<T: Serialize + DeserializeOwned>(
+        &self,
+        md: &str,
+        render: impl FnOnce(&str) -> Result<T>,
+    ) -> Result<T> {
+        let path = self.entry_path(md);
+        let source = digest([md.as_bytes()]);
+
+        // a missing, unreadable or mismatched entry is simply rendered again
+        if let Ok(content) = fs::read(&path)
+            && let Ok(cached) = serde_json::from_slice::<Entry<T>>(&content)
+            && cached.source == source
+        {
+            let _ = fs::File::options()
+                .append(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            return Ok(cached.rendered);
+        }
+
+        let entry = Entry {
+            source,
+            rendered: render(md)?,
+        };
+        let content = serde_json::to_vec(&entry).context("Failed to serialize cache entry")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write cache entry {path:?}"))?;
+        Ok(entry.rendered)
+    }
+}
+
+/// Mark the start of a build using the cache at `dir`, returning the time to later pass to
+/// [`prune`].
+///
+/// The time is taken from the filesystem rather than the system clock, since modification times
+/// may be slightly behind the latter.
+pub fn mark_build_start(dir: &Path) -> Result<SystemTime> {
+    let marker = dir.join(".build-start");
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create cache dir {dir:?}"))?;
+    fs::write(&marker, "").with_context(|| format!("Failed to write {marker:?}"))?;
+    fs::metadata(&marker)
+        .and_then(|meta| meta.modified())
+        .with_context(|| format!("Failed to read {marker:?}"))
+}
+
+/// Remove entries in the cache at `dir` that have not been used since `since`.
+pub fn prune(dir: &Path, since: SystemTime) -> Result<()> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(());
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let stale = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .is_ok_and(|modified| modified < since);
+        if stale {
+            fs::remove_file(entry.path())
+                .with_context(|| format!("Failed to remove cache entry {:?}", entry.path()))?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,66 +1,388 @@
 use anyhow::Context;
 use confique::{Config, Partial};
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::time::Instant;
+use walkdir::WalkDir;
 
 use crate::{
-    assets,
+    assets::{self, StaticContent},
+    cache,
     config::{Conf, PartialConf},
 };
 
 /// Build and write site to output directory.
-pub fn run(partial_config: PartialConf, output_dir: Option<PathBuf>) -> anyhow::Result<()> {
+pub fn run(
+    partial_config: PartialConf,
+    config_path: Option<&Path>,
+    output_dir: Option<PathBuf>,
+    drafts: bool,
+) -> anyhow::Result<()> {
     let config = Conf::from_partial(
         PartialConf {
             output_dir,
-            ..PartialConf::default_values()
+            drafts: drafts.then_some(true),
+            ..PartialConf::empty()
         }
         .with_fallback(partial_config),
     )?;
 
+    // the directory of the configuration file holds the rest of the project
+    let project_dir = config_path
+        .and_then(Path::parent)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    check_output_dir(&config, project_dir)?;
+
     let before_build = Instant::now();
+    let cache_start = config
+        .cache_dir
+        .as_deref()
+        .map(cache::mark_build_start)
+        .transpose()?;
+
+    let mut outputs = Outputs {
+        hard_link: config.hard_link_static_files,
+        ..Outputs::default()
+    };
+
+    if config.versions.is_empty() {
+        write_assets(
+            assets::get_all_assets(&config, None)?,
+            &config.output_dir,
+            &mut outputs,
+        )?;
+    } else {
+        build_versions(&config, &mut outputs)?;
+    }
+
+    let removed = outputs.remove_stale(&config.output_dir)?;
+
+    if let (Some(cache_dir), Some(cache_start)) = (&config.cache_dir, cache_start) {
+        cache::prune(cache_dir, cache_start)?;
+    }
+
+    println!(
+        "Site built in {:?} ({} files written, {} unchanged, {} removed)",
+        before_build.elapsed(),
+        outputs.written,
+        outputs.paths.len() - outputs.written,
+        removed,
+    );
+    Ok(())
+}
+
+/// Make sure that removing stale files from the output directory can't remove sources: it must not
+/// be or contain any docs_dir, or the directory of the configuration file.
+fn check_output_dir(config: &Conf, project_dir: &Path) -> anyhow::Result<()> {
+    // nothing to remove yet
+    let Ok(output_dir) = config.output_dir.canonicalize() else {
+        return Ok(());
+    };
+
+    let docs_dirs = std::iter::once(&config.docs_dir)
+        .chain(
+            config
+                .versions
+                .iter()
+                .filter_map(|version| version.docs_dir.as_ref()),
+        )
+        .map(|dir| ("docs_dir", dir.as_path()));
+    for (name, dir) in docs_dirs.chain([("the configuration file's directory", project_dir)]) {
+        if dir
+            .canonicalize()
+            .is_ok_and(|dir| dir.starts_with(&output_dir))
+        {
+            anyhow::bail!(
+                "output_dir {:?} contains {name} {dir:?}, whose files would be removed as stale",
+                config.output_dir
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Files in the output directory produced by the current build
+#[derive(Default)]
+struct Outputs {
+    paths: HashSet<PathBuf>,
+    written: usize,
+    /// Whether static files are hard-linked rather than copied
+    hard_link: bool,
+}
 
-    match fs::remove_dir_all(&config.output_dir) {
-        Ok(_) => {}
-        Err(e) => match e.kind() {
-            std::io::ErrorKind::NotFound => {}
-            _ => {
-                Err(e).with_context(|| {
-                    format!(
-                        "Failed to remove output directory: {:?}",
-                        &config.output_dir
-                    )
-                })?;
+impl Outputs {
+    /// Write `content` to `path`, unless the file already has exactly that content. Leaving
+    /// unchanged files alone keeps their timestamps, which makes for faster syncing on deploy.
+    fn write(&mut self, path: PathBuf, content: &[u8]) -> anyhow::Result<()> {
+        let unchanged = fs::metadata(&path).is_ok_and(|meta| meta.len() == content.len() as u64)
+            && fs::read(&path).is_ok_and(|existing| existing == content);
+
+        if !unchanged {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {parent:?}"))?;
             }
-        },
+            fs::write(&path, content).with_context(|| format!("Failed to write {path:?}"))?;
+            self.written += 1;
+        }
+
+        self.paths.insert(path);
+        Ok(())
     }
 
-    for asset in assets::get_all_assets(&config)? {
-        match asset {
-            assets::Asset::Page(page) => {
-                let output_path = config.output_dir.join(&page.url_path).join("index.html");
+    /// Publish the file at `source` as `path`, unless it already has the same size and
+    /// modification time. The file is reflinked or copied, or hard-linked if enabled and possible,
+    /// so that large files are never read into memory.
+    fn copy(&mut self, path: PathBuf, source: &Path) -> anyhow::Result<()> {
+        let source_meta =
+            fs::metadata(source).with_context(|| format!("Failed to read {source:?}"))?;
+        let same_size_and_time = fs::metadata(&path).is_ok_and(|meta| {
+            meta.len() == source_meta.len() && meta.modified().ok() == source_meta.modified().ok()
+        });
+        // replace hard links made while they were enabled
+        let unchanged = same_size_and_time
+            && (self.hard_link || !same_file::is_same_file(source, &path).unwrap_or(false));
 
-                if let Some(parent) = output_path.parent() {
-                    fs::create_dir_all(parent)
-                        .with_context(|| format!("Failed to create directory: {parent:?}"))?;
+        if !unchanged {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {parent:?}"))?;
+            }
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("Failed to replace {path:?}"));
                 }
-                fs::write(&output_path, &page.rendered)
-                    .with_context(|| format!("Failed to write HTML file: {output_path:?}"))?;
+                _ => {}
             }
-            assets::Asset::Static(static_asset) => {
-                let output_path = config.output_dir.join(&static_asset.url_path);
 
-                if let Some(parent) = output_path.parent() {
-                    fs::create_dir_all(parent)
-                        .with_context(|| format!("Failed to create directory: {parent:?}"))?;
+            if !self.hard_link || fs::hard_link(source, &path).is_err() {
+                reflink_copy::reflink_or_copy(source, &path)
+                    .with_context(|| format!("Failed to copy {source:?} to {path:?}"))?;
+                // so that the copy is seen as unchanged next time
+                if let Ok(modified) = source_meta.modified() {
+                    let _ = fs::File::options()
+                        .append(true)
+                        .open(&path)
+                        .and_then(|file| file.set_modified(modified));
+                }
+            }
+            self.written += 1;
+        }
+
+        self.paths.insert(path);
+        Ok(())
+    }
+
+    /// Remove files (and then empty directories) in `output_dir` that were not produced by this
+    /// build. Returns the number of files removed.
+    fn remove_stale(&self, output_dir: &Path) -> anyhow::Result<usize> {
+        let mut removed = 0;
+
+        for entry in WalkDir::new(output_dir).min_depth(1).contents_first(true) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) if e.io_error().map(std::io::Error::kind) == Some(ErrorKind::NotFound) => {
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read {output_dir:?}"));
+                }
+            };
+
+            if entry.file_type().is_dir() {
+                // only succeeds if empty
+                let _ = fs::remove_dir(entry.path());
+            } else if !self.paths.contains(entry.path()) {
+                fs::remove_file(entry.path())
+                    .with_context(|| format!("Failed to remove stale file {:?}", entry.path()))?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Write rendered assets into `output_dir`.
+fn write_assets(
+    all_assets: Vec<assets::Asset>,
+    output_dir: &Path,
+    outputs: &mut Outputs,
+) -> anyhow::Result<()> {
+    for asset in all_assets {
+        match asset {
+            assets::Asset::Page(page) => outputs.write(
+                output_dir.join(page.output_path()),
+                page.rendered.as_bytes(),
+            )?,
+            assets::Asset::Static(static_asset) => {
+                let path = output_dir.join(&static_asset.url_path);
+                match &static_asset.content {
+                    StaticContent::Generated(content) => outputs.write(path, content)?,
+                    StaticContent::File(source) => outputs.copy(path, source)?,
                 }
-                fs::write(&output_path, &static_asset.content)
-                    .with_context(|| format!("Failed to write static asset: {output_path:?}"))?;
             }
         }
     }
 
-    println!("Site built in {:?}", before_build.elapsed());
     Ok(())
 }
+
+/// Build each of `config.versions` into its own subdirectory of the output directory, along with
+/// a `versions.json` listing them and redirects for their aliases.
+fn build_versions(config: &Conf, outputs: &mut Outputs) -> anyhow::Result<()> {
+    let mut names = HashSet::new();
+    for name in config
+        .versions
+        .iter()
+        .flat_map(|version| std::iter::once(&version.name).chain(&version.aliases))
+    {
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            anyhow::bail!("Invalid version name or alias: {name:?}");
+        }
+        if !names.insert(name) {
+            anyhow::bail!("Version name or alias {name:?} is used more than once");
+        }
+    }
+
+    let root_path = config.root_path();
+    let mut versions_json = Vec::new();
+
+    for version in &config.versions {
+        let docs_dir = version.docs_dir.as_ref().unwrap_or(&config.docs_dir);
+
+        let extracted_dir = match &version.git_ref {
+            Some(git_ref) => Some(
+                extract_from_git(git_ref, docs_dir, &version.name)
+                    .with_context(|| format!("Failed to get docs of version {:?}", version.name))?,
+            ),
+            None => None,
+        };
+
+        let version_config = Conf {
+            docs_dir: extracted_dir
+                .as_ref()
+                .map_or_else(|| docs_dir.clone(), |dir| dir.join(docs_dir)),
+            ..config.clone()
+        };
+
+        // static files are copied from the extracted docs, so write before cleaning them up
+        let written = assets::get_all_assets(&version_config, Some(&version.name))
+            .and_then(|version_assets| {
+                let page_paths: Vec<(String, PathBuf)> = version_assets
+                    .iter()
+                    .filter_map(|asset| match asset {
+                        assets::Asset::Page(page) => {
+                            Some((page.url_path.clone(), page.output_path()))
+                        }
+                        assets::Asset::Static(_) => None,
+                    })
+                    .collect();
+
+                write_assets(
+                    version_assets,
+                    &config.output_dir.join(&version.name),
+                    outputs,
+                )?;
+                Ok(page_paths)
+            })
+            .with_context(|| format!("Failed to build version {:?}", version.name));
+
+        if let Some(extracted_dir) = extracted_dir {
+            let _ = fs::remove_dir_all(extracted_dir);
+        }
+        let page_paths = written?;
+
+        for alias in &version.aliases {
+            for (page_path, page_output_path) in &page_paths {
+                let target = format!("{root_path}{}/{page_path}", version.name);
+                write_redirect(
+                    outputs,
+                    config.output_dir.join(alias).join(page_output_path),
+                    &target,
+                )?;
+            }
+        }
+
+        versions_json.push(serde_json::json!({
+            "version": version.name,
+            "title": version.title.as_deref().unwrap_or(&version.name),
+            "aliases": version.aliases,
+        }));
+    }
+
+    outputs.write(
+        config.output_dir.join("versions.json"),
+        serde_json::to_string_pretty(&versions_json)
+            .context("Failed to serialize versions")?
+            .as_bytes(),
+    )?;
+
+    let default_version = &config.versions[0].name;
+    write_redirect(
+        outputs,
+        config.output_dir.join("index.html"),
+        &format!("{root_path}{default_version}/"),
+    )
+}
+
+/// Extract `path` as it is at `git_ref` of the repository in the current directory.
+///
+/// Returns a temporary directory, in which `path` can be found.
+fn extract_from_git(git_ref: &str, path: &Path, version_name: &str) -> anyhow::Result<PathBuf> {
+    let destination =
+        std::env::temp_dir().join(format!("picodocs-{}-{version_name}", std::process::id()));
+    fs::create_dir_all(&destination)
+        .with_context(|| format!("Failed to create directory: {destination:?}"))?;
+
+    let mut archive = Command::new("git")
+        .args(["archive", "--format=tar", git_ref, "--"])
+        .arg(path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run git")?;
+
+    let extracted = Command::new("tar")
+        .arg("-x")
+        .arg("-C")
+        .arg(&destination)
+        .stdin(archive.stdout.take().context("Failed to read git output")?)
+        .status()
+        .context("Failed to run tar")?;
+
+    if !archive.wait().context("Failed to run git")?.success() {
+        anyhow::bail!("git archive of {path:?} at {git_ref:?} failed");
+    }
+    if !extracted.success() {
+        anyhow::bail!("Failed to extract {path:?} at {git_ref:?}");
+    }
+
+    Ok(destination)
+}
+
+fn write_redirect(outputs: &mut Outputs, output_path: PathBuf, target: &str) -> anyhow::Result<()> {
+    outputs.write(
+        output_path,
+        format!(
+            r#"<!doctype html>
+<html>
+	<head>
+		<meta charset="utf-8" />
+		<title>Redirecting</title>
+		<link rel="canonical" href="{target}" />
+		<meta http-equiv="refresh" content="0; url={target}" />
+	</head>
+	<body>
+		<a href="{target}">Redirecting to {target}</a>
+	</body>
+</html>
+"#
+        )
+        .as_bytes(),
+    )
+}
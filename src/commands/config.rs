@@ -0,0 +1,56 @@
+use anyhow::Context;
+use confique::{Config, meta};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::config::{self, Conf, Layers};
+
+/// Print the configuration that commands would use, as YAML with a comment on where each value
+/// comes from.
+pub fn show(config_path: Option<&Path>, overrides: &[String]) -> anyhow::Result<()> {
+    let layers = Layers::load(config_path, overrides)?;
+
+    let to_value = |partial| serde_json::to_value(partial).context("Failed to serialize layer");
+    let mut sources = vec![
+        ("--set".to_string(), to_value(&layers.overrides)?),
+        ("environment".to_string(), to_value(&layers.env)?),
+    ];
+    if let Some((path, file)) = &layers.file {
+        sources.push((format!("{path:?}"), to_value(file)?));
+    }
+    sources.push(("default".to_string(), to_value(&layers.defaults)?));
+
+    let mut merged = layers.merge();
+    let nav_from_source = config::read_nav_source(&mut merged)?;
+    let config = Conf::from_partial(merged).context("Invalid configuration")?;
+    let config = serde_json::to_value(&config).context("Failed to serialize configuration")?;
+
+    for field in Conf::META.fields {
+        let source = if field.name == "nav" && nav_from_source {
+            Some("nav_source".to_string())
+        } else {
+            sources
+                .iter()
+                .find(|(_, layer)| !layer[field.name].is_null())
+                .map(|(source, _)| match (source.as_str(), field.kind) {
+                    ("environment", meta::FieldKind::Leaf { env: Some(env), .. }) => {
+                        env.to_string()
+                    }
+                    _ => source.clone(),
+                })
+        };
+
+        let mut entry = serde_json::Map::new();
+        entry.insert(field.name.to_string(), config[field.name].clone());
+        let yaml = serde_yaml::to_string(&Value::Object(entry))
+            .context("Failed to serialize configuration to YAML")?;
+
+        match source {
+            Some(source) => println!("# from {source}"),
+            None => println!("# not set"),
+        }
+        print!("{yaml}");
+    }
+
+    Ok(())
+}
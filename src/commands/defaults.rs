@@ -18,10 +18,10 @@ pub fn run(output_path: PathBuf, force: bool) -> anyhow::Result<()> {
 
     fs::write(
         &output_path,
-        match output_path.extension().unwrap().to_str().unwrap() {
-            "yml" | "yaml" => serde_yaml::to_string(&default_conf)
+        match output_path.extension().and_then(|ext| ext.to_str()) {
+            Some("yml" | "yaml") => serde_yaml::to_string(&default_conf)
                 .context("Failed to serialize default configuration to YAML")?,
-            "toml" => toml::to_string(&default_conf)
+            Some("toml") => toml::to_string(&default_conf)
                 .context("Failed to serialize default configuration to TOML")?,
             _ => anyhow::bail!(
                 "Unsupported file extension for output: {:?}. Supported extensions are .yml, .yaml, and .toml.",
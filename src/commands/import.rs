@@ -0,0 +1,463 @@
+use anyhow::Context;
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{config::NavItem, summary};
+
+/// Markdown extensions of MkDocs which picodocs supports out of the box
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "meta",
+    "tables",
+    "toc",
+    "pymdownx.snippets",
+    "pymdownx.tasklist",
+];
+
+/// Markdown extensions of MkDocs with a counterpart in picodocs that is written differently
+const EXTENSION_HINTS: &[(&str, &str)] = &[
+    ("admonition", "use the `note` shortcode"),
+    ("pymdownx.details", "use the `details` shortcode"),
+    ("pymdownx.tilde", "only `~~strikethrough~~` is supported"),
+];
+
+/// Plugins of MkDocs which picodocs has built in
+const SUPPORTED_PLUGINS: &[&str] = &["search"];
+
+/// Plugins of MkDocs with a counterpart in picodocs
+const PLUGIN_HINTS: &[(&str, &str)] = &[
+    ("i18n", "see the `languages` key"),
+    ("static-i18n", "see the `languages` key"),
+    ("mike", "see the `versions` key"),
+];
+
+/// Top-level keys of `mkdocs.yml` which are translated
+const MAPPED_KEYS: &[&str] = &[
+    "site_name",
+    "site_url",
+    "site_description",
+    "docs_dir",
+    "site_dir",
+    "exclude_docs",
+    "nav",
+    "theme",
+    "plugins",
+    "markdown_extensions",
+];
+
+/// The part of the configuration that can be carried over from MkDocs or mdBook
+#[derive(Serialize)]
+struct ImportedConf {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon_path: Option<String>,
+    docs_dir: PathBuf,
+    output_dir: PathBuf,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exclude: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nav_source: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nav: Option<Vec<NavItem>>,
+}
+
+/// Keys in sections of mdBook's `book.toml` which are translated
+const MDBOOK_MAPPED_KEYS: &[(&str, &[&str])] = &[
+    ("book", &["title", "description", "language", "src"]),
+    ("build", &["build-dir"]),
+    ("output.html", &["site-url"]),
+];
+
+/// Preprocessors of mdBook with a counterpart in picodocs
+const SUPPORTED_PREPROCESSORS: &[&str] = &["links"];
+
+/// Translate the MkDocs configuration at `mkdocs_config` into a picodocs configuration file, and
+/// report what could not be translated.
+pub fn mkdocs(
+    mkdocs_config: PathBuf,
+    output_path: Option<PathBuf>,
+    force: bool,
+) -> anyhow::Result<()> {
+    let mkdocs_dir = mkdocs_config.parent().unwrap_or(Path::new(""));
+    let output_path = check_output_path(output_path, mkdocs_dir, force)?;
+
+    let content = fs::read_to_string(&mkdocs_config)
+        .with_context(|| format!("Failed to read {mkdocs_config:?}"))?;
+    let mkdocs: Value = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse YAML in {mkdocs_config:?}"))?;
+    let mkdocs = strip_tags(mkdocs);
+    let Value::Mapping(mkdocs) = mkdocs else {
+        anyhow::bail!("{mkdocs_config:?} is not a map of keys to values");
+    };
+
+    let get_str = |key: &str| mkdocs.get(key).and_then(Value::as_str).map(str::to_string);
+    let theme = mkdocs.get("theme");
+    let theme_str = |key: &str| {
+        theme
+            .and_then(|theme| theme.get(key))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+
+    // relative to the MkDocs configuration, which may be elsewhere than the picodocs one
+    let output_base = output_path.parent().unwrap_or(Path::new(""));
+    let rebase = |dir: &str| relative_to(&mkdocs_dir.join(dir), output_base);
+
+    let mut unmapped = Vec::new();
+
+    let nav = match mkdocs.get("nav") {
+        Some(nav) => Some(
+            nav_items(nav, &mut unmapped)
+                .with_context(|| format!("Failed to translate `nav` of {mkdocs_config:?}"))?,
+        ),
+        None => None,
+    };
+
+    let imported = ImportedConf {
+        title: get_str("site_name"),
+        description: get_str("site_description"),
+        base_url: get_str("site_url"),
+        language: theme_str("language"),
+        icon_path: theme_str("favicon"),
+        docs_dir: rebase(get_str("docs_dir").as_deref().unwrap_or("docs")),
+        output_dir: rebase(get_str("site_dir").as_deref().unwrap_or("site")),
+        exclude: get_str("exclude_docs")
+            .map(|patterns| {
+                patterns
+                    .lines()
+                    .map(str::trim)
+                    .filter(|pattern| !pattern.is_empty() && !pattern.starts_with('#'))
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        nav_source: None,
+        nav,
+    };
+
+    for name in names(mkdocs.get("plugins")) {
+        if SUPPORTED_PLUGINS.contains(&name.as_str()) {
+            continue;
+        }
+        match PLUGIN_HINTS.iter().find(|(plugin, _)| *plugin == name) {
+            Some((_, hint)) => unmapped.push(format!("plugin `{name}`: {hint}")),
+            None => unmapped.push(format!("plugin `{name}` is not supported")),
+        }
+    }
+
+    for name in names(mkdocs.get("markdown_extensions")) {
+        if SUPPORTED_EXTENSIONS.contains(&name.as_str()) {
+            continue;
+        }
+        match EXTENSION_HINTS
+            .iter()
+            .find(|(extension, _)| *extension == name)
+        {
+            Some((_, hint)) => unmapped.push(format!("markdown extension `{name}`: {hint}")),
+            None => unmapped.push(format!("markdown extension `{name}` is not supported")),
+        }
+    }
+
+    if let Some(theme_name) =
+        theme_str("name").or_else(|| theme.and_then(Value::as_str).map(str::to_string))
+    {
+        unmapped.push(format!(
+            "theme `{theme_name}` is not supported, picodocs has its own page template"
+        ));
+    }
+
+    for key in mkdocs.keys().filter_map(Value::as_str) {
+        if !MAPPED_KEYS.contains(&key) {
+            unmapped.push(format!("`{key}` is ignored"));
+        }
+    }
+
+    write_config(&imported, &output_path, &unmapped)
+}
+
+/// Translate the mdBook configuration at `book_config` into a picodocs configuration file, with
+/// `nav` taken from the book's `SUMMARY.md`, and report what could not be translated.
+///
+/// If `nav_source` is set, the configuration reads `SUMMARY.md` on every build instead, so that
+/// the book can go on being edited as before.
+pub fn mdbook(
+    book_config: PathBuf,
+    output_path: Option<PathBuf>,
+    nav_source: bool,
+    force: bool,
+) -> anyhow::Result<()> {
+    const SUMMARY: &str = "SUMMARY.md";
+
+    let book_dir = book_config.parent().unwrap_or(Path::new(""));
+    let output_path = check_output_path(output_path, book_dir, force)?;
+
+    let content = fs::read_to_string(&book_config)
+        .with_context(|| format!("Failed to read {book_config:?}"))?;
+    let book: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse TOML in {book_config:?}"))?;
+
+    let section = |name: &str| {
+        name.split('.')
+            .try_fold(&book, |table, key| table.get(key)?.as_table())
+    };
+    let get_str = |section_name: &str, key: &str| {
+        section(section_name)
+            .and_then(|table| table.get(key))
+            .and_then(toml::Value::as_str)
+            .map(str::to_string)
+    };
+
+    let output_base = output_path.parent().unwrap_or(Path::new(""));
+    let src = get_str("book", "src").unwrap_or_else(|| "src".to_string());
+    let summary_path = book_dir.join(&src).join(SUMMARY);
+
+    let mut unmapped = Vec::new();
+
+    for (section_name, mapped) in MDBOOK_MAPPED_KEYS {
+        for key in section(section_name)
+            .into_iter()
+            .flat_map(|table| table.keys())
+        {
+            if !mapped.contains(&key.as_str()) {
+                unmapped.push(format!("`{section_name}.{key}` is ignored"));
+            }
+        }
+    }
+    for name in section("preprocessor")
+        .into_iter()
+        .flat_map(|table| table.keys())
+    {
+        if !SUPPORTED_PREPROCESSORS.contains(&name.as_str()) {
+            unmapped.push(format!("preprocessor `{name}` is not supported"));
+        }
+    }
+    for name in section("output").into_iter().flat_map(|table| table.keys()) {
+        if name != "html" {
+            unmapped.push(format!("output `{name}` is not supported"));
+        }
+    }
+
+    let imported = ImportedConf {
+        title: get_str("book", "title"),
+        description: get_str("book", "description"),
+        base_url: get_str("output.html", "site-url"),
+        language: get_str("book", "language"),
+        icon_path: None,
+        docs_dir: relative_to(&book_dir.join(&src), output_base),
+        output_dir: relative_to(
+            &book_dir.join(get_str("build", "build-dir").as_deref().unwrap_or("book")),
+            output_base,
+        ),
+        exclude: Vec::new(),
+        nav_source: nav_source.then(|| PathBuf::from(SUMMARY)),
+        nav: if nav_source {
+            None
+        } else {
+            Some(summary::load(&summary_path)?)
+        },
+    };
+
+    write_config(&imported, &output_path, &unmapped)
+}
+
+/// Where to write the imported configuration: `output_path`, or else `picodocs.yml` in `dir`.
+/// Fails if the file exists, unless `force` is set.
+fn check_output_path(
+    output_path: Option<PathBuf>,
+    dir: &Path,
+    force: bool,
+) -> anyhow::Result<PathBuf> {
+    let output_path = output_path.unwrap_or_else(|| dir.join("picodocs.yml"));
+    if output_path.exists() && !force {
+        anyhow::bail!(
+            "{:?} already exists. Aborting. Use --force to overwrite.",
+            &output_path
+        );
+    }
+    Ok(output_path)
+}
+
+/// Write `imported` to `output_path`, in the format matching its extension, and list what was
+/// `unmapped`.
+fn write_config(
+    imported: &ImportedConf,
+    output_path: &Path,
+    unmapped: &[String],
+) -> anyhow::Result<()> {
+    let serialized = match output_path.extension().and_then(|ext| ext.to_str()) {
+        Some("yml" | "yaml") => {
+            serde_yaml::to_string(imported).context("Failed to serialize configuration to YAML")?
+        }
+        Some("toml") => {
+            toml::to_string(imported).context("Failed to serialize configuration to TOML")?
+        }
+        _ => anyhow::bail!(
+            "Unsupported file extension for output: {:?}. Supported extensions are .yml, .yaml, and .toml.",
+            output_path
+        ),
+    };
+    fs::write(output_path, serialized)
+        .with_context(|| format!("Failed to write configuration to {output_path:?}"))?;
+
+    println!("Configuration written to {output_path:?}");
+    if !unmapped.is_empty() {
+        println!("\nThe following could not be carried over:");
+        for item in unmapped {
+            println!("  - {item}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Translate the `nav` of MkDocs, whose items are a page, a titled page or link, or a titled
+/// section with items of its own.
+fn nav_items(nav: &Value, unmapped: &mut Vec<String>) -> anyhow::Result<Vec<NavItem>> {
+    let Value::Sequence(items) = nav else {
+        anyhow::bail!("Expected a list of navigation items");
+    };
+
+    let mut translated = Vec::new();
+    for item in items {
+        match item {
+            Value::String(path) => translated.push(NavItem::Path(path.clone())),
+            Value::Mapping(entry) => {
+                for (title, target) in entry {
+                    let title = title
+                        .as_str()
+                        .context("Titles of navigation items must be strings")?
+                        .to_string();
+                    match target {
+                        Value::String(target) => translated.push(NavItem::ExternalLink(
+                            BTreeMap::from([(title, target.clone())]),
+                        )),
+                        Value::Sequence(_) => {
+                            let children = nav_items(target, unmapped)
+                                .with_context(|| format!("In section {title:?}"))?;
+                            translated.push(NavItem::Section(BTreeMap::from([(title, children)])));
+                        }
+                        _ => unmapped.push(format!("navigation item {title:?} has no target")),
+                    }
+                }
+            }
+            other => anyhow::bail!("Unexpected navigation item: {other:?}"),
+        }
+    }
+
+    Ok(translated)
+}
+
+/// Names in `plugins` or `markdown_extensions`, which are lists of names or of single-entry maps
+/// from name to options, or (for plugins) a map from name to options.
+fn names(list: Option<&Value>) -> Vec<String> {
+    let keys = |map: &serde_yaml::Mapping| -> Vec<String> {
+        map.keys()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    };
+
+    match list {
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .flat_map(|item| match item {
+                Value::String(name) => vec![name.clone()],
+                Value::Mapping(map) => keys(map),
+                _ => Vec::new(),
+            })
+            .collect(),
+        Some(Value::Mapping(map)) => keys(map),
+        _ => Vec::new(),
+    }
+}
+
+/// Drop YAML tags such as `!ENV` and `!!python/name:`, which MkDocs configurations may use, keeping
+/// the values they are attached to.
+fn strip_tags(value: Value) -> Value {
+    match value {
+        Value::Tagged(tagged) => strip_tags(tagged.value),
+        Value::Sequence(items) => Value::Sequence(items.into_iter().map(strip_tags).collect()),
+        Value::Mapping(map) => Value::Mapping(
+            map.into_iter()
+                .map(|(key, value)| (strip_tags(key), strip_tags(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// `path` relative to `base`, if it is inside of it.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    match (std::path::absolute(path), std::path::absolute(base)) {
+        (Ok(path), Ok(base)) => path
+            .strip_prefix(&base)
+            .map(Path::to_path_buf)
+            .unwrap_or(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translate(nav: &str) -> (anyhow::Result<Value>, Vec<String>) {
+        let mut unmapped = Vec::new();
+        let nav: Value = serde_yaml::from_str(nav).unwrap();
+        let items =
+            nav_items(&nav, &mut unmapped).map(|items| serde_yaml::to_value(items).unwrap());
+        (items, unmapped)
+    }
+
+    fn yaml(s: &str) -> Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn translates_pages_links_and_sections() {
+        let nav = "
+- index.md
+- Guide: guide.md
+- GitHub: https://github.com/
+- Reference:
+    - reference/index.md
+    - CLI: reference/cli.md
+    - Nested:
+        - reference/nested.md
+";
+        let (items, unmapped) = translate(nav);
+        assert_eq!(items.unwrap(), yaml(nav));
+        assert!(unmapped.is_empty());
+    }
+
+    #[test]
+    fn reports_items_without_target() {
+        let (items, unmapped) = translate("- index.md\n- Empty:\n- Other: 3\n");
+        assert_eq!(items.unwrap(), yaml("- index.md"));
+        assert_eq!(
+            unmapped,
+            [
+                "navigation item \"Empty\" has no target",
+                "navigation item \"Other\" has no target",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_nav() {
+        assert!(translate("index.md").0.is_err());
+        assert!(translate("- 3").0.is_err());
+        assert!(translate("- 3: page.md").0.is_err());
+        let error = translate("- Section:\n    - 3\n").0.unwrap_err();
+        assert_eq!(error.to_string(), "In section \"Section\"");
+    }
+}
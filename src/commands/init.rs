@@ -0,0 +1,276 @@
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{args::ConfigFormat, assets::BUILTIN_TEMPLATES, config::NavItem};
+
+const INDEX_MD: &str = r#"# Welcome
+
+This is the front page of your documentation. Edit `docs/index.md` to change it.
+
+## Next steps
+
+- Run `picodocs serve` to preview the site while you write.
+- Read the [guide](guide/) to see how pages are organized.
+- Run `picodocs build` to render the site into `public/`.
+"#;
+
+const GUIDE_INDEX_MD: &str = r#"# Guide
+
+Each directory in `docs/` is a section of the site, and its `index.md` is the landing page of
+that section.
+
+- [Writing pages](writing-pages)
+"#;
+
+const WRITING_PAGES_MD: &str = r#"---
+title: Writing pages
+---
+
+# Writing pages
+
+Pages are written in Markdown, with support for tables, task lists and more.
+
+{% note(kind="tip") %}
+Shortcodes like this one add elements that Markdown has no syntax for.
+{% end %}
+
+Link to other pages by their path, like [the front page](../).
+"#;
+
+/// Only the settings a new project is likely to change, rather than everything `defaults` dumps
+#[derive(Serialize)]
+struct InitConf<'a> {
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    base_url: &'a str,
+    docs_dir: &'a str,
+    output_dir: &'a str,
+    nav: Vec<NavItem>,
+}
+
+/// Answers to the questions asked by `init`, which flags may give up front
+struct Answers {
+    title: String,
+    description: Option<String>,
+    templates: bool,
+    workflow: bool,
+}
+
+/// Scaffold a new documentation project in `dir`.
+pub fn run(
+    dir: PathBuf,
+    format: ConfigFormat,
+    title: Option<String>,
+    templates: bool,
+    no_workflow: bool,
+    yes: bool,
+    force: bool,
+) -> anyhow::Result<()> {
+    let default_title = fs::canonicalize(&dir)
+        .ok()
+        .or_else(|| Some(dir.clone()))
+        .and_then(|dir| Some(dir.file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "Documentation".to_string());
+
+    let answers = if yes || !std::io::stdin().is_terminal() {
+        Answers {
+            title: title.unwrap_or(default_title),
+            description: None,
+            templates,
+            workflow: !no_workflow,
+        }
+    } else {
+        let mut input = std::io::stdin().lock();
+        Answers {
+            title: match title {
+                Some(title) => title,
+                None => ask(&mut input, "Title of the site", &default_title)?,
+            },
+            description: Some(ask(&mut input, "Description (optional)", "")?)
+                .filter(|description| !description.is_empty()),
+            templates: templates
+                || confirm(
+                    &mut input,
+                    "Add the page template to templates/ to customize it?",
+                    false,
+                )?,
+            workflow: !no_workflow
+                && confirm(
+                    &mut input,
+                    "Add a GitHub Actions workflow publishing to GitHub Pages?",
+                    true,
+                )?,
+        }
+    };
+
+    let (config_name, picodocs) = match format {
+        ConfigFormat::Yaml => ("picodocs.yml", "picodocs"),
+        ConfigFormat::Toml => ("picodocs.toml", "picodocs --config picodocs.toml"),
+    };
+    let config = InitConf {
+        title: &answers.title,
+        description: answers.description.as_deref(),
+        base_url: "/",
+        docs_dir: "docs",
+        output_dir: "public",
+        nav: vec![
+            NavItem::Path("index.md".to_string()),
+            NavItem::Section(BTreeMap::from([(
+                "Guide".to_string(),
+                vec![
+                    NavItem::Path("guide/index.md".to_string()),
+                    NavItem::Path("guide/writing-pages.md".to_string()),
+                ],
+            )])),
+        ],
+    };
+    let config_content = match format {
+        ConfigFormat::Yaml => {
+            serde_yaml::to_string(&config).context("Failed to serialize configuration to YAML")?
+        }
+        ConfigFormat::Toml => {
+            toml::to_string(&config).context("Failed to serialize configuration to TOML")?
+        }
+    };
+
+    let mut files: Vec<(PathBuf, String)> = vec![
+        (PathBuf::from(config_name), config_content),
+        (PathBuf::from("docs/index.md"), INDEX_MD.to_string()),
+        (
+            PathBuf::from("docs/guide/index.md"),
+            GUIDE_INDEX_MD.to_string(),
+        ),
+        (
+            PathBuf::from("docs/guide/writing-pages.md"),
+            WRITING_PAGES_MD.to_string(),
+        ),
+    ];
+    if answers.templates {
+        files.extend(
+            BUILTIN_TEMPLATES
+                .iter()
+                .map(|(name, content)| (Path::new("templates").join(name), content.to_string())),
+        );
+    }
+    if answers.workflow {
+        files.push((
+            PathBuf::from(".github/workflows/docs.yml"),
+            workflow(config_name, picodocs, answers.templates),
+        ));
+    }
+
+    let existing: Vec<PathBuf> = files
+        .iter()
+        .map(|(path, _)| dir.join(path))
+        .filter(|path| path.exists())
+        .collect();
+    if !existing.is_empty() && !force {
+        anyhow::bail!(
+            "{existing:?} already exist{}. Aborting. Use --force to overwrite.",
+            if existing.len() == 1 { "s" } else { "" }
+        );
+    }
+
+    for (path, content) in &files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {parent:?}"))?;
+        }
+        fs::write(&path, content).with_context(|| format!("Failed to write {path:?}"))?;
+        println!("Created {path:?}");
+    }
+
+    if dir == Path::new(".") {
+        println!("\nRun `{picodocs} serve` to preview the site");
+    } else {
+        println!("\nRun `{picodocs} serve` in {dir:?} to preview the site");
+    }
+
+    Ok(())
+}
+
+/// GitHub Actions workflow which builds the site and publishes it to GitHub Pages
+fn workflow(config_name: &str, picodocs: &str, templates: bool) -> String {
+    format!(
+        r#"name: Publish docs
+
+on:
+  push:
+    branches:
+      - main
+    paths:
+      - docs/**
+      - {config_name}{templates_path}
+      - .github/workflows/docs.yml
+  workflow_dispatch:
+
+jobs:
+  deploy:
+    runs-on: ubuntu-24.04
+    permissions:
+      id-token: write # to use actions/deploy-pages
+      pages: write # to push to a github pages site
+    steps:
+      - uses: actions/checkout@v4
+
+      - uses: cargo-bins/cargo-binstall@main
+
+      - run: cargo binstall --no-confirm picodocs
+
+      - run: {picodocs} build
+
+      - uses: actions/upload-pages-artifact@v3
+        with:
+          path: public
+
+      - id: deployment
+        uses: actions/deploy-pages@v4
+"#,
+        templates_path = if templates {
+            "\n      - templates/**"
+        } else {
+            ""
+        },
+    )
+}
+
+/// Ask for a value on stdin, falling back to `default` if none is given.
+fn ask(input: &mut impl BufRead, question: &str, default: &str) -> anyhow::Result<String> {
+    if default.is_empty() {
+        print!("{question}: ");
+    } else {
+        print!("{question} [{default}]: ");
+    }
+    std::io::stdout()
+        .flush()
+        .context("Failed to write to stdout")?;
+
+    let mut answer = String::new();
+    input
+        .read_line(&mut answer)
+        .context("Failed to read from stdin")?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// Ask a yes or no question on stdin.
+fn confirm(input: &mut impl BufRead, question: &str, default: bool) -> anyhow::Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    loop {
+        match ask(input, &format!("{question} [{hint}]"), "")?
+            .to_lowercase()
+            .as_str()
+        {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer y or n"),
+        }
+    }
+}
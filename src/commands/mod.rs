@@ -1,3 +1,8 @@
 pub mod build;
+pub mod config;
 pub mod defaults;
+pub mod import;
+pub mod init;
+pub mod preview;
+pub mod reference;
 pub mod serve;
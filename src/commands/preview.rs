@@ -0,0 +1,191 @@
+use anyhow::Context;
+use axum::{
+    Router,
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::Response,
+    routing::get,
+};
+use confique::Config;
+use percent_encoding::percent_decode_str;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{
+    commands::serve::{self, serve_file},
+    config::{Conf, PartialConf},
+};
+
+/// Precompressed variants of files which are served in their place, if accepted by the client, in
+/// order of preference
+const PRECOMPRESSED: &[(&str, &str)] = &[("br", "br"), ("zstd", "zst"), ("gzip", "gz")];
+
+/// What is needed to answer requests
+struct Preview {
+    output_dir: PathBuf,
+    /// Path of `base_url`, which the site is served under
+    root_path: String,
+}
+
+/// Serve the output directory as built by `build`, the way a static file server would.
+pub fn run(
+    partial_config: PartialConf,
+    address: String,
+    open: bool,
+    next_free_port: bool,
+) -> anyhow::Result<()> {
+    let config = Conf::from_partial(partial_config).context("Invalid configuration")?;
+
+    if !config.output_dir.is_dir() {
+        anyhow::bail!(
+            "Output directory {:?} does not exist, run `picodocs build` first",
+            config.output_dir
+        );
+    }
+
+    let preview = Arc::new(Preview {
+        output_dir: config.output_dir.clone(),
+        root_path: config.root_path(),
+    });
+    let root_path = preview.root_path.clone();
+
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async move {
+        let app = Router::new()
+            .fallback(get(serve_output_dir))
+            .with_state(preview);
+
+        let listener = serve::bind(&address, next_free_port).await?;
+        let urls = serve::reachable_urls(listener.local_addr()?, &root_path);
+        println!("Previewing {:?}", config.output_dir);
+        serve::print_urls(&urls);
+
+        if open {
+            open::that(&urls[0])
+                .with_context(|| format!("Failed to open browser at {}", &urls[0]))?;
+        }
+
+        axum::serve(listener, app)
+            .with_graceful_shutdown(serve::shutdown_signal())
+            .await
+            .context("Failed to start server")
+    })
+}
+
+async fn serve_output_dir(State(preview): State<Arc<Preview>>, req: Request) -> Response {
+    let raw_path = req.uri().path();
+    let decoded_path = percent_decode_str(raw_path).decode_utf8_lossy();
+
+    // the root of the site without trailing slash, or outside of it entirely
+    let Some(rel) = decoded_path.strip_prefix(&preview.root_path) else {
+        return if decoded_path == "/" || format!("{decoded_path}/") == preview.root_path {
+            redirect(&preview.root_path)
+        } else {
+            not_found(&preview, "").await
+        };
+    };
+
+    let rel_path = Path::new(rel);
+    if !rel_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return not_found(&preview, rel).await;
+    }
+
+    let mut path = preview.output_dir.join(rel_path);
+    if path.is_dir() {
+        if !rel.is_empty() && !rel.ends_with('/') {
+            return redirect(&match req.uri().query() {
+                Some(query) => format!("{raw_path}/?{query}"),
+                None => format!("{raw_path}/"),
+            });
+        }
+        path.push("index.html");
+    }
+
+    if path.is_file() {
+        serve_negotiated(&path, req.headers()).await
+    } else {
+        not_found(&preview, rel).await
+    }
+}
+
+/// Serve the file at `path`, or a precompressed variant of it accepted by the client.
+async fn serve_negotiated(path: &Path, headers: &HeaderMap) -> Response {
+    let mime_type = mime_guess::from_path(path).first_or_octet_stream();
+
+    let accepted: Vec<&str> = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next()?;
+            // `q=0` means not acceptable
+            let refused = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            (!refused).then_some(name)
+        })
+        .collect();
+
+    for (encoding, extension) in PRECOMPRESSED {
+        if !accepted.contains(encoding) {
+            continue;
+        }
+        let mut compressed = path.as_os_str().to_owned();
+        compressed.push(format!(".{extension}"));
+        let compressed = PathBuf::from(compressed);
+
+        if compressed.is_file() {
+            let mut response = serve_file(&compressed, mime_type.as_ref(), headers).await;
+            let response_headers = response.headers_mut();
+            response_headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+            response_headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+            return response;
+        }
+    }
+
+    let mut response = serve_file(path, mime_type.as_ref(), headers).await;
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    response
+}
+
+/// Respond with `404.html`, preferring one in the same top-level directory as `rel`, which is
+/// where translations are placed.
+async fn not_found(preview: &Preview, rel: &str) -> Response {
+    let candidates = rel
+        .split_once('/')
+        .map(|(dir, _)| preview.output_dir.join(dir).join("404.html"))
+        .into_iter()
+        .chain([preview.output_dir.join("404.html")]);
+
+    for candidate in candidates {
+        if candidate.is_file() {
+            let mut response = serve_negotiated(&candidate, &HeaderMap::new()).await;
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            return response;
+        }
+    }
+
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(format!("{rel} not found")))
+        .unwrap()
+}
+
+fn redirect(location: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,51 @@
+use anyhow::Context;
+use clap::CommandFactory;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::{
+    args::{Args, ConfigReferenceFormat, ReferenceKind},
+    reference,
+};
+
+pub fn run(kind: ReferenceKind) -> anyhow::Result<()> {
+    match kind {
+        ReferenceKind::Cli { output_path } => write_output(
+            output_path,
+            &reference::cli_markdown(&Args::command(), &[], 1)?,
+        ),
+        ReferenceKind::Config {
+            format,
+            output_path,
+        } => {
+            let content = match format {
+                ConfigReferenceFormat::Markdown => {
+                    format!("# Configuration\n\n{}", reference::config_markdown(2))
+                }
+                ConfigReferenceFormat::JsonSchema => {
+                    serde_json::to_string_pretty(&reference::config_json_schema())
+                        .context("Failed to serialize JSON Schema")?
+                        + "\n"
+                }
+            };
+            write_output(output_path, &content)
+        }
+    }
+}
+
+/// Write `content` to `output_path`, or stdout if not given.
+fn write_output(output_path: Option<PathBuf>, content: &str) -> anyhow::Result<()> {
+    match output_path {
+        Some(output_path) => {
+            fs::write(&output_path, content)
+                .with_context(|| format!("Failed to write reference to {output_path:?}"))?;
+            println!("Reference written to {output_path:?}");
+        }
+        None => std::io::stdout()
+            .write_all(content.as_bytes())
+            .context("Failed to write reference to stdout")?,
+    }
+
+    Ok(())
+}
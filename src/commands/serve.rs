@@ -2,22 +2,33 @@ use anyhow::Context;
 use axum::{
     Router,
     body::{self, Body},
-    http::StatusCode,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
     routing::get,
 };
 use confique::Config;
-use notify::RecursiveMode;
-use notify_debouncer_full::{DebounceEventResult, new_debouncer};
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
+use httpdate::HttpDate;
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{DebounceEventResult, Debouncer, RecommendedCache, new_debouncer};
+use percent_encoding::percent_decode_str;
+use std::collections::{HashMap, HashSet};
+use std::io::SeekFrom;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use time::format_description::BorrowedFormatItem;
 use time::macros::format_description;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::net::TcpListener;
+use tokio_util::io::ReaderStream;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    assets::{Asset, InMemoryAsset, get_all_assets},
-    config::{Conf, PartialConf},
+    assets::{self, Asset, AssetChanges, InMemoryAsset, Site, StaticContent},
+    cache,
+    config::{self, Conf, PartialConf},
+    exclude::{Exclusions, IGNORE_FILES},
 };
 
 const SIMPLE_TIME_FORMAT: &[BorrowedFormatItem<'_>] =
@@ -27,77 +38,411 @@ use axum::extract::{Request, State};
 
 type AssetMapLock = Arc<RwLock<HashMap<String, InMemoryAsset>>>;
 
+/// How a request is answered, decided while holding the lock on the assets
+enum Resolved {
+    Html(StatusCode, String),
+    Generated(mime_guess::Mime, Vec<u8>),
+    File(PathBuf, mime_guess::Mime),
+    /// To the directory form of the path, with a trailing slash
+    Redirect,
+    NotFound,
+}
+
+/// Find the asset at `path` (without leading slash), the same way a static file server would
+/// find it in the built output: pages are directories containing `index.html`.
+fn resolve(map: &HashMap<String, InMemoryAsset>, path: &str) -> Resolved {
+    let dir = path
+        .strip_suffix("index.html")
+        .filter(|dir| dir.is_empty() || dir.ends_with('/'))
+        .or_else(|| (path.is_empty() || path.ends_with('/')).then_some(path));
+
+    if let Some(dir) = dir {
+        return match map.get(dir.trim_end_matches('/')) {
+            Some(InMemoryAsset::Page(page)) if !page.url_path.ends_with(".html") => {
+                Resolved::Html(StatusCode::OK, page.rendered.clone())
+            }
+            _ => Resolved::NotFound,
+        };
+    }
+
+    match map.get(path) {
+        Some(InMemoryAsset::Page(page)) if page.url_path.ends_with(".html") => {
+            Resolved::Html(StatusCode::OK, page.rendered.clone())
+        }
+        Some(InMemoryAsset::Page(_)) => Resolved::Redirect,
+        Some(InMemoryAsset::Static(s)) => match &s.content {
+            StaticContent::Generated(content) => {
+                Resolved::Generated(s.mime_type.clone(), content.clone())
+            }
+            StaticContent::File(file) => Resolved::File(file.clone(), s.mime_type.clone()),
+        },
+        None => Resolved::NotFound,
+    }
+}
+
+/// The `404.md` page to show for `path`, preferring one in the same top-level directory, which is
+/// where translations are placed.
+fn not_found_page(map: &HashMap<String, InMemoryAsset>, path: &str) -> Option<String> {
+    let in_dir = path
+        .split_once('/')
+        .map(|(dir, _)| format!("{dir}/{}", assets::NOT_FOUND_PATH));
+
+    in_dir
+        .iter()
+        .map(String::as_str)
+        .chain([assets::NOT_FOUND_PATH])
+        .find_map(|not_found_path| match map.get(not_found_path) {
+            Some(InMemoryAsset::Page(page)) => Some(page.rendered.clone()),
+            _ => None,
+        })
+}
+
 async fn serve_from_memory(
     State(asset_map_lock): State<AssetMapLock>,
     req: Request,
 ) -> impl IntoResponse {
-    let path = req.uri().path().trim_start_matches('/');
+    let decoded_path = percent_decode_str(req.uri().path()).decode_utf8_lossy();
+    let root_path = ROOT_PATH.read().unwrap().clone();
 
-    let map = asset_map_lock.read().unwrap();
+    // `None` outside of the site, which is served at the path of `base_url`
+    let path = decoded_path.strip_prefix(root_path.as_str());
+    if path.is_none() && (decoded_path == "/" || format!("{decoded_path}/") == root_path) {
+        return redirect(&root_path);
+    }
 
-    match map.get(path) {
-        Some(asset) => match asset {
-            InMemoryAsset::Page(p) => Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", "text/html")
-                .body(Body::from(p.rendered.clone()))
-                .unwrap()
-                .into_response(),
-            InMemoryAsset::Static(s) => Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", s.mime_type.as_ref())
-                .body(Body::from(s.content.clone()))
-                .unwrap()
-                .into_response(),
-        },
-        None => Response::builder()
+    let resolved = {
+        let map = asset_map_lock.read().unwrap();
+
+        match path.map_or(Resolved::NotFound, |path| resolve(&map, path)) {
+            Resolved::NotFound => match not_found_page(&map, path.unwrap_or("")) {
+                Some(rendered) => Resolved::Html(StatusCode::NOT_FOUND, rendered),
+                None => Resolved::NotFound,
+            },
+            resolved => resolved,
+        }
+    };
+
+    match resolved {
+        Resolved::Html(status, rendered) => Response::builder()
+            .status(status)
+            .header("Content-Type", "text/html")
+            .body(Body::from(rendered))
+            .unwrap(),
+        Resolved::Generated(mime_type, content) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", mime_type.as_ref())
+            .body(Body::from(content))
+            .unwrap(),
+        Resolved::File(file, mime_type) => {
+            serve_file(&file, mime_type.as_ref(), req.headers()).await
+        }
+        Resolved::Redirect => redirect(&match req.uri().query() {
+            Some(query) => format!("{}/?{query}", req.uri().path()),
+            None => format!("{}/", req.uri().path()),
+        }),
+        Resolved::NotFound => Response::builder()
             .status(StatusCode::NOT_FOUND)
-            .body(Body::from(format!("{path} not found")))
-            .unwrap()
-            .into_response(),
+            .body(Body::from(format!("{decoded_path} not found")))
+            .unwrap(),
+    }
+}
+
+fn redirect(location: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Respond with the file at `path`, streamed from disk. Supports conditional requests using
+/// `ETag`/`Last-Modified`, and requests for a single byte range.
+pub async fn serve_file(path: &Path, mime_type: &str, headers: &HeaderMap) -> Response {
+    let opened = match tokio::fs::File::open(path).await {
+        Ok(file) => file.metadata().await.map(|meta| (file, meta)),
+        Err(e) => Err(e),
+    };
+    let (mut file, meta) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from(format!("Failed to read {path:?}: {e}")))
+                .unwrap();
+        }
+    };
+
+    let len = meta.len();
+    let modified = meta.modified().ok().map(HttpDate::from);
+    let etag = format!(
+        "\"{len:x}-{:x}\"",
+        meta.modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_nanos())
+    );
+
+    let header_str = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+    };
+    let matches_etag = |value: &str| {
+        value
+            .split(',')
+            .any(|tag| tag.trim().trim_start_matches("W/") == etag)
+    };
+
+    let not_modified = match header_str(header::IF_NONE_MATCH) {
+        Some(value) => value.trim() == "*" || matches_etag(value),
+        None => header_str(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.parse::<HttpDate>().ok())
+            .zip(modified)
+            .is_some_and(|(since, modified)| modified <= since),
+    };
+
+    let mut response = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(modified) = modified {
+        response = response.header(header::LAST_MODIFIED, modified.to_string());
+    }
+
+    if not_modified {
+        return response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    // a range is only honored if the file is still the one the client has part of
+    let range_applies = header_str(header::IF_RANGE).is_none_or(|value| {
+        matches_etag(value)
+            || value
+                .parse::<HttpDate>()
+                .ok()
+                .is_some_and(|date| Some(date) == modified)
+    });
+    let range = header_str(header::RANGE)
+        .filter(|_| range_applies)
+        .map(|value| parse_range(value, len));
+
+    let (start, end) = match range {
+        None | Some(RangeRequest::Unsupported) => (0, len),
+        Some(RangeRequest::Unsatisfiable) => {
+            return response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+                .body(Body::empty())
+                .unwrap();
+        }
+        Some(RangeRequest::Satisfiable(start, end)) => {
+            response = response.status(StatusCode::PARTIAL_CONTENT).header(
+                header::CONTENT_RANGE,
+                format!("bytes {start}-{}/{len}", end - 1),
+            );
+            (start, end)
+        }
+    };
+
+    if start > 0
+        && let Err(e) = file.seek(SeekFrom::Start(start)).await
+    {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(format!("Failed to read {path:?}: {e}")))
+            .unwrap();
+    }
+
+    response
+        .header(header::CONTENT_TYPE, mime_type)
+        .header(header::CONTENT_LENGTH, end - start)
+        .body(Body::from_stream(ReaderStream::new(file.take(end - start))))
+        .unwrap()
+}
+
+/// A `Range` header, resolved against the length of a file
+enum RangeRequest {
+    /// Bytes `start..end` of the file
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+    /// Multiple ranges or units other than bytes, which are answered with the whole file
+    Unsupported,
+}
+
+fn parse_range(value: &str, len: u64) -> RangeRequest {
+    let Some(range) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Unsupported;
+    };
+    if range.contains(',') {
+        return RangeRequest::Unsupported;
+    }
+    let Some((start, end)) = range.split_once('-') else {
+        return RangeRequest::Unsupported;
+    };
+
+    let (start, end) = match (start.trim().parse::<u64>(), end.trim().parse::<u64>()) {
+        // the last `end` bytes
+        (Err(_), Ok(suffix)) if start.trim().is_empty() => (len.saturating_sub(suffix), len),
+        (Ok(start), Ok(end)) => (start, end.saturating_add(1).min(len)),
+        (Ok(start), Err(_)) if end.trim().is_empty() => (start, len),
+        _ => return RangeRequest::Unsupported,
+    };
+
+    if start >= end {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Satisfiable(start, end)
     }
 }
-fn rebuild_in_memory_assets(
-    config: &Conf,
+
+/// Apply `changes` to the assets in `store`. Returns the url paths of assets which changed, or
+/// `None` if all of them were replaced.
+fn update_in_memory_assets(
     store: &RwLock<HashMap<String, InMemoryAsset>>,
-) -> anyhow::Result<()> {
+    changes: AssetChanges,
+) -> anyhow::Result<Option<Vec<String>>> {
     let mut map = store
         .write()
         .map_err(|e| anyhow::anyhow!("Failed to acquire write lock: {}", e))?;
 
-    *map = get_all_assets(config)?
-        .into_iter()
-        .map(|asset| match asset {
-            Asset::Page(page) => (page.url_path.clone(), InMemoryAsset::Page(page)),
-            Asset::Static(static_asset) => (
-                static_asset.url_path.clone(),
-                InMemoryAsset::Static(static_asset),
-            ),
-        })
-        .collect::<HashMap<_, _>>();
+    let in_memory = |asset| match asset {
+        Asset::Page(page) => (page.url_path.clone(), InMemoryAsset::Page(page)),
+        Asset::Static(static_asset) => (
+            static_asset.url_path.clone(),
+            InMemoryAsset::Static(static_asset),
+        ),
+    };
+
+    match changes {
+        AssetChanges::All(all_assets) => {
+            *map = all_assets
+                .into_iter()
+                .map(in_memory)
+                .collect::<HashMap<_, _>>();
+            Ok(None)
+        }
+        AssetChanges::Some { updated, removed } => {
+            for url_path in &removed {
+                map.remove(url_path);
+            }
+            let mut changed = removed;
+            for (url_path, asset) in updated.into_iter().map(in_memory) {
+                changed.push(url_path.clone());
+                map.insert(url_path, asset);
+            }
+            Ok(Some(changed))
+        }
+    }
+}
 
-    Ok(())
+/// Whether a change to `path` should trigger a rebuild, given the files included by pages. All
+/// paths are absolute.
+fn affects_site(
+    path: &Path,
+    docs_dir: &Path,
+    exclusions: &Exclusions,
+    includes: &HashSet<PathBuf>,
+) -> bool {
+    match path.strip_prefix(docs_dir) {
+        // only metadata of the directory itself
+        Ok(rel) if rel.as_os_str().is_empty() => false,
+        // included files may be excluded from the site themselves, like snippets
+        Ok(rel) => {
+            includes.contains(path)
+                || rel
+                    .file_name()
+                    .is_some_and(|name| IGNORE_FILES.iter().any(|f| name == *f))
+                || !exclusions.is_excluded(rel, path.is_dir())
+        }
+        // included files outside of docs_dir
+        Err(_) => includes.contains(path),
+    }
+}
+
+/// All files included by pages in `store`, as absolute paths.
+fn included_files(store: &RwLock<HashMap<String, InMemoryAsset>>) -> HashSet<PathBuf> {
+    let map = store.read().unwrap();
+
+    map.values()
+        .filter_map(|asset| match asset {
+            InMemoryAsset::Page(page) => Some(page.included_files.iter()),
+            InMemoryAsset::Static(_) => None,
+        })
+        .flatten()
+        .filter_map(|path| std::path::absolute(path).ok())
+        .collect()
 }
 
 use once_cell::sync::Lazy;
 use tokio::sync::broadcast;
-static RELOAD_TX: Lazy<broadcast::Sender<()>> = Lazy::new(|| {
+static RELOAD_TX: Lazy<broadcast::Sender<LiveMessage>> = Lazy::new(|| {
     let (tx, _) = broadcast::channel(100);
     tx
 });
 
+/// The error of the latest rebuild, if it failed. Sent to browsers as they connect, so the error
+/// is shown on pages loaded after the rebuild.
+static BUILD_ERROR: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+/// The path the site is served at, which is [`Conf::root_path`]
+static ROOT_PATH: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new("/".to_string()));
+
+/// Cancelled once the server is shutting down
+static SHUTDOWN: Lazy<CancellationToken> = Lazy::new(CancellationToken::new);
+
+/// Message sent to browsers over the live reload channel
+#[derive(Clone)]
+enum LiveMessage {
+    Reload,
+    /// The url paths of assets which changed, or `None` if every page did
+    Changed(Option<Vec<String>>),
+    /// Show the error of a failed rebuild
+    BuildError(String),
+}
+
 use axum::response::sse::{Event, KeepAlive, Sse};
 use futures::{Stream, StreamExt};
 use std::convert::Infallible;
 use tokio_stream::wrappers::BroadcastStream;
 async fn sse_handler() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    let stream = BroadcastStream::new(RELOAD_TX.subscribe()).map(|_| {
-        Result::<Event, Infallible>::Ok(
-            Event::default()
-                .retry(Duration::from_millis(250))
-                .data("reload"),
+    let build_error = BUILD_ERROR.lock().unwrap().clone();
+
+    let stream = futures::stream::iter(build_error.map(LiveMessage::BuildError))
+        .chain(
+            BroadcastStream::new(RELOAD_TX.subscribe())
+                // reload if messages were missed
+                .map(|message| message.unwrap_or(LiveMessage::Reload)),
         )
-    });
+        .map(|message| {
+            Result::<Event, Infallible>::Ok(match message {
+                LiveMessage::Reload => Event::default()
+                    .retry(Duration::from_millis(250))
+                    .data("reload"),
+                LiveMessage::Changed(url_paths) => {
+                    // as seen by the browser, below the root path
+                    let root_path = ROOT_PATH.read().unwrap().clone();
+                    let url_paths = url_paths.map(|url_paths| {
+                        url_paths
+                            .iter()
+                            .map(|url_path| {
+                                format!("{root_path}{url_path}")
+                                    .trim_matches('/')
+                                    .to_string()
+                            })
+                            .collect::<Vec<_>>()
+                    });
+                    Event::default()
+                        .event("change")
+                        .data(serde_json::to_string(&url_paths).unwrap_or_default())
+                }
+                LiveMessage::BuildError(error) => Event::default()
+                    .event("build-error")
+                    .data(serde_json::to_string(&error).unwrap_or_default()),
+            })
+        })
+        // end the stream on shutdown, which otherwise waits for it
+        .take_until(SHUTDOWN.cancelled());
     Sse::new(stream).keep_alive(
         KeepAlive::new()
             .interval(Duration::from_secs(1))
@@ -107,16 +452,17 @@ async fn sse_handler() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
 
 use axum::middleware::Next;
 
-static LIVERELOAD_SCRIPT_BYTES: &[u8] = br#"<script>
-    new EventSource('/~~~picodocs-reload').onmessage = (e) => {
-        if (e.data === 'reload') window.location.reload()
-    };
-</script>"#;
+static LIVERELOAD_SCRIPT_BYTES: &[u8] = concat!(
+    "<script>\n",
+    include_str!("../../templates/livereload.js"),
+    "</script>"
+)
+.as_bytes();
 
 async fn append_livereload_script(request: Request, next: Next) -> Response {
     let response = next.run(request).await;
 
-    if response.status() != StatusCode::OK {
+    if !matches!(response.status(), StatusCode::OK | StatusCode::NOT_FOUND) {
         return response;
     }
 
@@ -144,82 +490,362 @@ async fn append_livereload_script(request: Request, next: Next) -> Response {
 
 use std::sync::RwLock;
 
-pub fn run(partial_config: PartialConf, address: String, open: bool) -> anyhow::Result<()> {
-    let config = Arc::new(Conf::from_partial(partial_config).unwrap());
-    let docs_dir = config.docs_dir.clone();
+pub fn run(
+    partial_config: PartialConf,
+    config_path: Option<PathBuf>,
+    overrides: Vec<String>,
+    address: String,
+    open: bool,
+    next_free_port: bool,
+) -> anyhow::Result<()> {
+    let mut config = Conf {
+        drafts: true,
+        ..Conf::from_partial(partial_config).context("Invalid configuration")?
+    };
+    *ROOT_PATH.write().unwrap() = config.root_path();
 
     let asset_map = Arc::new(RwLock::new(HashMap::new()));
-
-    let config_for_thread = Arc::clone(&config);
     let asset_map_for_thread = Arc::clone(&asset_map);
 
-    std::thread::spawn(move || {
+    let mut site = load_site(&config)?;
+    update_in_memory_assets(
+        &asset_map,
+        AssetChanges::All(site.assets(&config, None, None)?),
+    )?;
+
+    let (tx, rx) = std::sync::mpsc::channel::<DebounceEventResult>();
+    // to wake up the watcher on shutdown
+    let wake_watcher = tx.clone();
+
+    let mut debouncer = new_debouncer(Duration::from_millis(250), None, tx)
+        .context("Failed to set up file watcher")?;
+    debouncer
+        .watch(&config.docs_dir, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch docs_dir {:?}", config.docs_dir))?;
+
+    // the configuration file and those it extends
+    let mut config_files = match &config_path {
+        Some(path) => config::files(path)?,
+        None => Vec::new(),
+    };
+    let mut watched_config_dirs = HashSet::new();
+    watch_config_dirs(&mut debouncer, &config_files, &mut watched_config_dirs);
+
+    let watcher = std::thread::spawn(move || {
         use notify::EventKind::{Create, Modify, Remove};
         use time::OffsetDateTime;
 
-        let mut debouncer = new_debouncer(
-            Duration::from_millis(250),
-            None,
-            move |res: DebounceEventResult| {
-                if let Ok(events) = &res {
-                    if events
-                        .iter()
-                        .any(|e| matches!(e.event.kind, Create(_) | Modify(_) | Remove(_)))
-                    {
-                        let now = OffsetDateTime::now_local()
-                            .unwrap_or_else(|_| OffsetDateTime::now_utc())
-                            .time()
-                            .format(SIMPLE_TIME_FORMAT)
-                            .unwrap_or_else(|_| "?".to_string());
-
-                        println!("[{now}] Change detected, rebuilding...");
-
-                        if let Err(e) =
-                            rebuild_in_memory_assets(&config_for_thread, &asset_map_for_thread)
-                        {
-                            eprintln!("Error rebuilding assets: {}", e);
-                        } else if let Err(e) = RELOAD_TX.send(()) {
-                            eprintln!("Error sending reload message: {}", e);
-                        }
-                    }
-                }
-            },
-        )
-        .expect("Failed to set up file watcher");
+        let template_dirs: Vec<PathBuf> = ["templates", "shortcodes"]
+            .into_iter()
+            .filter_map(|dir| std::path::absolute(dir).ok())
+            .collect();
 
-        debouncer
-            .watch(&docs_dir, RecursiveMode::Recursive)
-            .expect("Failed to watch docs_dir");
+        // template directories, and files outside of docs_dir which are pulled in by include
+        // directives
+        let mut watched = HashSet::new();
 
         loop {
-            std::thread::park();
+            let docs_dir = std::path::absolute(&config.docs_dir).unwrap_or_default();
+            let includes = included_files(&asset_map_for_thread);
+            for path in template_dirs
+                .iter()
+                .filter(|dir| dir.is_dir())
+                .chain(&includes)
+            {
+                if path.starts_with(&docs_dir) || !watched.insert(path.clone()) {
+                    continue;
+                }
+                if let Err(e) = debouncer.watch(path, RecursiveMode::Recursive) {
+                    eprintln!("Error watching {path:?}: {e}");
+                }
+            }
+
+            let Ok(res) = rx.recv() else {
+                break;
+            };
+            if SHUTDOWN.is_cancelled() {
+                break;
+            }
+
+            let Ok(events) = res else {
+                continue;
+            };
+
+            let mut changed_paths: Vec<PathBuf> = events
+                .iter()
+                .filter(|e| matches!(e.event.kind, Create(_) | Modify(_) | Remove(_)))
+                .flat_map(|e| &e.event.paths)
+                .filter_map(|path| std::path::absolute(path).ok())
+                .collect();
+            changed_paths.sort();
+            changed_paths.dedup();
+
+            // the navigation is read from `nav_source` along with the configuration
+            let nav_source = config
+                .nav_source
+                .as_ref()
+                .and_then(|nav_source| std::path::absolute(config.docs_dir.join(nav_source)).ok());
+            let config_changed = config_files
+                .iter()
+                .chain(&nav_source)
+                .any(|path| changed_paths.contains(path));
+            let templates_changed = changed_paths
+                .iter()
+                .any(|path| template_dirs.iter().any(|dir| path.starts_with(dir)));
+
+            // re-read on every change, in case ignore files were edited
+            let exclusions = match Exclusions::new(&config) {
+                Ok(exclusions) => exclusions,
+                Err(e) => {
+                    eprintln!("Error reading exclusions: {e:?}");
+                    continue;
+                }
+            };
+            changed_paths.retain(|path| affects_site(path, &docs_dir, &exclusions, &includes));
+
+            if !config_changed && !templates_changed && changed_paths.is_empty() {
+                continue;
+            }
+
+            let now = OffsetDateTime::now_local()
+                .unwrap_or_else(|_| OffsetDateTime::now_utc())
+                .time()
+                .format(SIMPLE_TIME_FORMAT)
+                .unwrap_or_else(|_| "?".to_string());
+
+            println!("[{now}] Change detected, rebuilding...");
+            let before_rebuild = Instant::now();
+
+            let updated = if config_changed || templates_changed {
+                // everything may have changed, so rebuild the whole site
+                (|| {
+                    if config_changed {
+                        let new_config =
+                            Conf::from_partial(config::load(config_path.as_deref(), &overrides)?)
+                                .context("Invalid configuration")?;
+                        let new_config = Conf {
+                            drafts: true,
+                            ..new_config
+                        };
+
+                        if new_config.docs_dir != config.docs_dir {
+                            let _ = debouncer.unwatch(&config.docs_dir);
+                            debouncer
+                                .watch(&new_config.docs_dir, RecursiveMode::Recursive)
+                                .with_context(|| {
+                                    format!("Failed to watch {:?}", new_config.docs_dir)
+                                })?;
+                        }
+                        *ROOT_PATH.write().unwrap() = new_config.root_path();
+                        config = new_config;
+
+                        // `extends` may have changed
+                        if let Some(path) = &config_path {
+                            config_files = config::files(path)?;
+                            watch_config_dirs(
+                                &mut debouncer,
+                                &config_files,
+                                &mut watched_config_dirs,
+                            );
+                        }
+                    }
+                    if templates_changed {
+                        assets::reload_templates()?;
+                    }
+
+                    site = load_site(&config)?;
+                    update_in_memory_assets(
+                        &asset_map_for_thread,
+                        AssetChanges::All(site.assets(&config, None, None)?),
+                    )?;
+                    // the whole page may look different
+                    Ok(LiveMessage::Reload)
+                })()
+            } else {
+                site.update(&config, &exclusions, &changed_paths)
+                    .and_then(|changes| update_in_memory_assets(&asset_map_for_thread, changes))
+                    .map(LiveMessage::Changed)
+            };
+
+            let message = match updated {
+                Ok(message) => {
+                    println!("Rebuilt in {:?}", before_rebuild.elapsed());
+                    *BUILD_ERROR.lock().unwrap() = None;
+                    message
+                }
+                Err(e) => {
+                    // each cause on its own line, without any backtrace
+                    let error = e
+                        .chain()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join("\n  caused by: ");
+                    eprintln!("Error rebuilding assets: {error}");
+                    *BUILD_ERROR.lock().unwrap() = Some(error.clone());
+                    LiveMessage::BuildError(error)
+                }
+            };
+            if let Err(e) = RELOAD_TX.send(message) {
+                eprintln!("Error sending reload message: {}", e);
+            }
         }
     });
 
     let rt = tokio::runtime::Runtime::new()?;
-    rt.block_on(async move {
-        rebuild_in_memory_assets(&config, &asset_map)?;
-
+    let served = rt.block_on(async move {
         let app = Router::new()
             .fallback(get(serve_from_memory))
             .with_state(Arc::clone(&asset_map))
             .layer(axum::middleware::from_fn(append_livereload_script))
             .route("/~~~picodocs-reload", get(sse_handler));
 
-        let listener = tokio::net::TcpListener::bind(&address)
-            .await
-            .with_context(|| format!("Failed to bind to address: {address}"))?;
+        let listener = bind(&address, next_free_port).await?;
+        let root_path = ROOT_PATH.read().unwrap().clone();
+        let urls = reachable_urls(listener.local_addr()?, &root_path);
+        print_urls(&urls);
 
         if open {
-            open::that(format!("http://{}", &address))
-                .with_context(|| format!("Failed to open browser at http://{}", &address))?;
+            open::that(&urls[0])
+                .with_context(|| format!("Failed to open browser at {}", &urls[0]))?;
         }
 
-        println!("Serving at http://{}", &address);
-        let _ = RELOAD_TX.send(());
+        let _ = RELOAD_TX.send(LiveMessage::Reload);
 
         axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_signal())
             .await
             .context("Failed to start server")
-    })
+    });
+
+    SHUTDOWN.cancel();
+    let _ = wake_watcher.send(Ok(Vec::new()));
+    let _ = watcher.join();
+
+    served
+}
+
+/// Watch the directories of `config_files` which aren't watched yet. Directories rather than the
+/// files themselves, since editors may replace a file when saving it.
+fn watch_config_dirs(
+    debouncer: &mut Debouncer<RecommendedWatcher, RecommendedCache>,
+    config_files: &[PathBuf],
+    watched: &mut HashSet<PathBuf>,
+) {
+    for path in config_files {
+        if let Some(dir) = path.parent()
+            && watched.insert(dir.to_path_buf())
+            && let Err(e) = debouncer.watch(dir, RecursiveMode::NonRecursive)
+        {
+            eprintln!("Error watching configuration file {path:?}: {e}");
+        }
+    }
+}
+
+/// Load and render the whole site, and remove the entries of the render cache which it didn't use,
+/// like a build does. Otherwise the cache would keep growing while serving.
+fn load_site(config: &Conf) -> anyhow::Result<Site> {
+    let cache_start = config
+        .cache_dir
+        .as_deref()
+        .map(cache::mark_build_start)
+        .transpose()?;
+
+    let site = Site::load(config)?;
+
+    if let (Some(cache_dir), Some(cache_start)) = (&config.cache_dir, cache_start) {
+        cache::prune(cache_dir, cache_start)?;
+    }
+    Ok(site)
+}
+
+/// Bind to `address`. If `next_free_port` is set and its port is taken, the following ports are
+/// tried.
+pub async fn bind(address: &str, next_free_port: bool) -> anyhow::Result<TcpListener> {
+    const ATTEMPTS: u16 = 20;
+
+    let host_and_port = address
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)));
+    let (host, first_port) = match host_and_port {
+        Some(host_and_port) if next_free_port => host_and_port,
+        _ => {
+            return TcpListener::bind(address)
+                .await
+                .with_context(|| format!("Failed to bind to address: {address}"));
+        }
+    };
+    // IPv6 addresses are written in brackets
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    for port in first_port..first_port.saturating_add(ATTEMPTS) {
+        match TcpListener::bind((host, port)).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+                eprintln!("Port {port} is in use, trying the next one");
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to bind to {host}:{port}"));
+            }
+        }
+    }
+
+    anyhow::bail!("No free port found after trying {ATTEMPTS} ports from {address}")
+}
+
+/// URLs at which the server listening on `local_addr` can be reached, followed by `path`. When
+/// listening on all interfaces, that is the address of each of them.
+pub fn reachable_urls(local_addr: SocketAddr, path: &str) -> Vec<String> {
+    let port = local_addr.port();
+    let url = |ip: IpAddr| {
+        if ip.is_loopback() {
+            format!("http://localhost:{port}{path}")
+        } else {
+            format!("http://{}{path}", SocketAddr::new(ip, port))
+        }
+    };
+
+    if !local_addr.ip().is_unspecified() {
+        return vec![url(local_addr.ip())];
+    }
+
+    let mut ips: Vec<IpAddr> = if_addrs::get_if_addrs()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|interface| interface.ip())
+        .filter(|ip| match ip {
+            // IPv4 only when listening on 0.0.0.0
+            IpAddr::V4(_) => true,
+            // link-local addresses can't be used without naming the interface
+            IpAddr::V6(ip) => local_addr.is_ipv6() && (ip.segments()[0] & 0xffc0) != 0xfe80,
+        })
+        .collect();
+    // localhost first
+    ips.sort_by_key(|ip| !ip.is_loopback());
+
+    let mut urls: Vec<String> = ips.into_iter().map(url).collect();
+    urls.dedup();
+    if urls.is_empty() {
+        urls.push(url(IpAddr::from([127, 0, 0, 1])));
+    }
+    urls
+}
+
+pub fn print_urls(urls: &[String]) {
+    match urls {
+        [url] => println!("Serving at {url}"),
+        urls => {
+            println!("Serving at:");
+            for url in urls {
+                println!("  {url}");
+            }
+        }
+    }
+}
+
+/// Wait for Ctrl-C, and then signal everything to shut down.
+pub async fn shutdown_signal() {
+    if tokio::signal::ctrl_c().await.is_ok() {
+        println!("Shutting down...");
+    }
+    SHUTDOWN.cancel();
 }
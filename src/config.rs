@@ -1,9 +1,19 @@
-use confique::Config;
+use anyhow::Context;
+use confique::{Config, Partial};
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
-use std::{collections::BTreeMap, path::PathBuf};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+use crate::summary;
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(untagged)]
 pub enum NavItem {
     Path(String),
@@ -11,36 +21,526 @@ pub enum NavItem {
     Section(BTreeMap<String, Vec<NavItem>>),
 }
 
-#[derive(Config, Clone, Debug, Serialize)]
+/// Settings for one of the languages of a multilingual site
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Language {
+    /// Shown in the language switcher, e.g. "Svenska". Defaults to the language code
+    pub name: Option<String>,
+
+    /// Overrides `title` for pages in this language
+    pub title: Option<String>,
+
+    /// Overrides `description` for pages in this language
+    pub description: Option<String>,
+}
+
+/// A version of the documentation, for sites documenting several releases
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Version {
+    /// Used in URLs and in the version selector, e.g. "2.1"
+    pub name: String,
+
+    /// Shown in the version selector instead of `name`
+    pub title: Option<String>,
+
+    /// Git tag, branch or commit of the repository holding the configuration file, to build the
+    /// docs from. If not set, the docs are built from the working tree
+    pub git_ref: Option<String>,
+
+    /// Directory to build this version from, instead of `docs_dir`
+    pub docs_dir: Option<PathBuf>,
+
+    /// Other names for this version, such as "latest". Pages under an alias redirect to the
+    /// same page of this version
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Config, Clone, Debug, Serialize, JsonSchema)]
+#[config(partial_attr(derive(Serialize)))]
 pub struct Conf {
+    /// Name of the site, shown in the header and in page titles
+    #[config(env = "PICODOCS_TITLE", parse_env = parse_value)]
     pub title: Option<String>,
 
     /// Used as favicon, among other places
+    #[config(env = "PICODOCS_ICON_PATH", parse_env = parse_value)]
     pub icon_path: Option<PathBuf>,
 
+    /// Default description of pages, used in the description meta tag
+    #[config(env = "PICODOCS_DESCRIPTION", parse_env = parse_value)]
     pub description: Option<String>,
 
     /// Sitemap will only generate if this is a full/absolute URL e.g. https://www.example.com/
     #[config(default = "/")]
+    #[config(env = "PICODOCS_BASE_URL", parse_env = parse_value)]
     pub base_url: String,
 
+    /// Language of the site, used as the `lang` attribute of the HTML
     #[config(default = "en")]
+    #[config(env = "PICODOCS_LANGUAGE", parse_env = parse_value)]
     pub language: String,
 
+    /// Additional languages of the site, keyed by language code. Pages are translated by adding
+    /// e.g. `page.sv.md` next to `page.md`, or by placing them in `docs_dir/sv/`. An entry for
+    /// `language` itself may be added to name the default language
+    #[config(default = {})]
+    #[config(env = "PICODOCS_LANGUAGES", parse_env = parse_value)]
+    pub languages: BTreeMap<String, Language>,
+
     /// Root directory of markdown documentation
     #[config(default = "docs")]
+    #[config(env = "PICODOCS_DOCS_DIR", parse_env = parse_value)]
     pub docs_dir: PathBuf,
 
     /// Where to place rendered site files
     #[config(default = "public")]
+    #[config(env = "PICODOCS_OUTPUT_DIR", parse_env = parse_value)]
     pub output_dir: PathBuf,
 
+    /// Where to cache rendered markdown between builds, e.g. `.cache/picodocs`. Entries that go
+    /// unused during a build are removed. Caching is disabled if not set
+    #[config(env = "PICODOCS_CACHE_DIR", parse_env = parse_value)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Hard-link static files into the output directory instead of copying them, which is faster
+    /// and saves space for large files. Only enable this if nothing edits the output directory in
+    /// place, since that would edit the files in the docs directory as well
+    #[config(default = false)]
+    #[config(env = "PICODOCS_HARD_LINK_STATIC_FILES", parse_env = parse_value)]
+    pub hard_link_static_files: bool,
+
     /// Follow symbolic links when traversing the docs directory
     #[config(default = false)]
+    #[config(env = "PICODOCS_FOLLOW_LINKS", parse_env = parse_value)]
     pub follow_links: bool,
 
-    /// (If defined) controls the sitemap structure
+    /// Files in the docs directory to leave out of the site, as gitignore-style patterns relative
+    /// to `docs_dir`, e.g. `*.csv` or `drafts/`. Patterns in `.gitignore` and `.picodocsignore`
+    /// files in `docs_dir` and the directory of the configuration file are also excluded
+    #[config(default = [])]
+    #[config(env = "PICODOCS_EXCLUDE", parse_env = parse_value)]
+    pub exclude: Vec<String>,
+
+    /// Publish files and directories starting with a dot
+    #[config(default = false)]
+    #[config(env = "PICODOCS_INCLUDE_DOTFILES", parse_env = parse_value)]
+    pub include_dotfiles: bool,
+
+    /// Publish pages marked with `draft: true` in their front matter. Always enabled by `serve`
+    #[config(default = false)]
+    #[config(env = "PICODOCS_DRAFTS", parse_env = parse_value)]
+    pub drafts: bool,
+
+    /// Pages to leave out of the search index. Each entry excludes the page with that path
+    /// (relative to `docs_dir`) and all pages below it, e.g. `changelog.md` or `reference`
+    #[config(default = [])]
+    #[config(env = "PICODOCS_SEARCH_EXCLUDE", parse_env = parse_value)]
+    pub search_exclude: Vec<String>,
+
+    /// Versions of the documentation to build, each into `output_dir/<name>/`. The root of the
+    /// site redirects to the first version
+    #[config(default = [])]
+    #[config(env = "PICODOCS_VERSIONS", parse_env = parse_value)]
+    pub versions: Vec<Version>,
+
+    /// Markdown file to read `nav` from, relative to `docs_dir`, in the format of mdBook's
+    /// `SUMMARY.md`. The file itself is not published. Ignored if `nav` is set
+    #[config(env = "PICODOCS_NAV_SOURCE", parse_env = parse_value)]
+    pub nav_source: Option<PathBuf>,
+
+    /// Navigation shown in the sidebar, instead of the layout of `docs_dir`. Items are paths of
+    /// pages relative to `docs_dir`, maps from a title to a page or a URL, or maps from a title
+    /// to the items of a section. Pages that aren't published are left out
+    #[config(env = "PICODOCS_NAV", parse_env = parse_value)]
     pub nav: Option<Vec<NavItem>>,
 }
 
+/// Names of the configuration file looked for by [`discover`], in order of preference
+pub const FILE_NAMES: &[&str] = &["picodocs.yml", "picodocs.yaml", "picodocs.toml"];
+
+/// Find the configuration file in the current directory, or else the closest parent directory
+/// that has one.
+pub fn discover() -> Option<PathBuf> {
+    let current_dir = std::env::current_dir().ok()?;
+    let found = current_dir.ancestors().find_map(|dir| {
+        FILE_NAMES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    })?;
+
+    // keep paths short when the config is in the current directory
+    Some(match found.strip_prefix(&current_dir) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => found,
+    })
+}
+
+/// The directory picodocs was run from, if [`enter_project_dir`] left it
+static INVOKED_FROM: OnceCell<PathBuf> = OnceCell::new();
+
+/// Make the directory of the configuration file at `path` the current directory, since the
+/// directories in it and the files used alongside it (includes, templates, shortcodes and ignore
+/// files) are relative to that directory. Returns the path of the configuration file from there.
+pub fn enter_project_dir(path: &Path) -> anyhow::Result<PathBuf> {
+    if !path.is_file() {
+        anyhow::bail!("Configuration file {path:?} does not exist");
+    }
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Ok(path.to_path_buf());
+    };
+    if dir.as_os_str().is_empty() {
+        return Ok(path.to_path_buf());
+    }
+
+    let invoked_from = std::env::current_dir().context("Failed to get current directory")?;
+    std::env::set_current_dir(dir).with_context(|| format!("Failed to enter directory {dir:?}"))?;
+    let _ = INVOKED_FROM.set(invoked_from);
+
+    Ok(PathBuf::from(file_name))
+}
+
+/// Load the configuration from `--set` overrides, environment variables, the configuration file at
+/// `path` (if any) and defaults, in that order of precedence.
+pub fn load(path: Option<&Path>, overrides: &[String]) -> anyhow::Result<PartialConf> {
+    let mut partial = Layers::load(path, overrides)?.merge();
+    read_nav_source(&mut partial)?;
+    Ok(partial)
+}
+
+/// Set `nav` from `nav_source`, unless `nav` is already set. Returns whether it was.
+pub fn read_nav_source(partial: &mut PartialConf) -> anyhow::Result<bool> {
+    if partial.nav.is_none()
+        && let (Some(nav_source), Some(docs_dir)) = (&partial.nav_source, &partial.docs_dir)
+    {
+        let nav_source = docs_dir.join(nav_source);
+        partial.nav = Some(
+            summary::load(&nav_source)
+                .with_context(|| format!("Failed to read `nav_source` {nav_source:?}"))?,
+        );
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// The sources the configuration is merged from
+pub struct Layers {
+    /// From `--set key=value` arguments
+    pub overrides: PartialConf,
+    /// From `PICODOCS_*` environment variables
+    pub env: PartialConf,
+    /// The configuration file and what it sets
+    pub file: Option<(PathBuf, PartialConf)>,
+    pub defaults: PartialConf,
+}
+
+impl Layers {
+    /// Directories in the configuration file, and the default ones, are relative to the directory
+    /// of the configuration file. Those given by `--set` or environment variables are relative to
+    /// the directory picodocs was run from.
+    pub fn load(path: Option<&Path>, overrides: &[String]) -> anyhow::Result<Self> {
+        let mut overrides = parse_overrides(overrides)?;
+        let mut env = PartialConf::from_env().context("Invalid configuration in environment")?;
+        let mut defaults = PartialConf::default_values();
+
+        if let Some(invoked_from) = INVOKED_FROM.get() {
+            relative_to(&mut overrides, invoked_from);
+            relative_to(&mut env, invoked_from);
+        }
+
+        let file = match path {
+            Some(path) => {
+                let mut file = load_file(path)?;
+                if let Some(base) = path.parent().filter(|base| !base.as_os_str().is_empty()) {
+                    relative_to(&mut file, base);
+                    relative_to(&mut defaults, base);
+                }
+                Some((path.to_path_buf(), file))
+            }
+            None => None,
+        };
+
+        Ok(Layers {
+            overrides,
+            env,
+            file,
+            defaults,
+        })
+    }
+
+    pub fn merge(self) -> PartialConf {
+        let merged = self.overrides.with_fallback(self.env);
+        match self.file {
+            Some((_, file)) => merged.with_fallback(file),
+            None => merged,
+        }
+        .with_fallback(self.defaults)
+    }
+}
+
+fn relative_to(partial: &mut PartialConf, base: &Path) {
+    partial.docs_dir = partial.docs_dir.take().map(|dir| base.join(dir));
+    partial.output_dir = partial.output_dir.take().map(|dir| base.join(dir));
+    partial.cache_dir = partial.cache_dir.take().map(|dir| base.join(dir));
+}
+
+/// Parse `key=value` overrides given with `--set`, where later ones take precedence.
+fn parse_overrides(overrides: &[String]) -> anyhow::Result<PartialConf> {
+    let mut partial = PartialConf::empty();
+
+    for item in overrides {
+        let (key, value) = item
+            .split_once('=')
+            .with_context(|| format!("Invalid override {item:?}, expected key=value"))?;
+        let key = key.trim();
+        if !Conf::META.fields.iter().any(|field| field.name == key) {
+            anyhow::bail!("Unknown configuration key {key:?} in override {item:?}");
+        }
+
+        let mut first_error = None;
+        let parsed = value_candidates(value)
+            .into_iter()
+            .find_map(|candidate| {
+                serde_json::from_value::<PartialConf>(serde_json::json!({ key: candidate }))
+                    .map_err(|e| first_error.get_or_insert(e))
+                    .ok()
+            })
+            .ok_or_else(|| first_error.expect("there is always a candidate"))
+            .with_context(|| format!("Invalid value in override {item:?}"))?;
+
+        partial = parsed.with_fallback(partial);
+    }
+
+    Ok(partial)
+}
+
+/// Parse a value given in an environment variable: as YAML if that gives a value of the right
+/// type, or else as a plain string, or else as a comma-separated list.
+fn parse_value<T: DeserializeOwned>(value: &str) -> Result<T, serde_json::Error> {
+    let mut first_error = None;
+    for candidate in value_candidates(value) {
+        match serde_json::from_value(candidate) {
+            Ok(parsed) => return Ok(parsed),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.expect("there is always a candidate"))
+}
+
+/// The ways a value given on the command line or in an environment variable may be meant, in
+/// order of preference. This lets e.g. `true` be a boolean, while `*.csv` can still be a pattern.
+fn value_candidates(value: &str) -> Vec<serde_json::Value> {
+    use serde_json::Value;
+
+    let mut candidates = Vec::new();
+    if let Ok(parsed) = serde_yaml::from_str::<Value>(value) {
+        candidates.push(parsed);
+    }
+    candidates.push(Value::String(value.to_string()));
+    candidates.push(Value::Array(if value.trim().is_empty() {
+        Vec::new()
+    } else {
+        value
+            .split(',')
+            .map(|item| Value::String(item.trim().to_string()))
+            .collect()
+    }));
+    candidates
+}
+
+/// Read the configuration file at `path`, which may be YAML or TOML, along with the files it
+/// `extends`.
+pub fn load_file(path: &Path) -> anyhow::Result<PartialConf> {
+    let (value, _) = load_value(path, &mut Vec::new())?;
+    serde_path_to_error::deserialize(value)
+        .with_context(|| format!("Failed to load configuration from {path:?}"))
+}
+
+/// The configuration file at `path`, followed by all the files it `extends` directly or indirectly,
+/// as absolute paths.
+pub fn files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let (_, files) = load_value(path, &mut Vec::new())?;
+    Ok(files)
+}
+
+/// Read the configuration file at `path` as a generic value, with the files it `extends` merged
+/// in. `chain` holds the files currently being read, to detect cycles.
+///
+/// Returns the value along with all files that were read, as absolute paths.
+fn load_value(
+    path: &Path,
+    chain: &mut Vec<PathBuf>,
+) -> anyhow::Result<(serde_json::Value, Vec<PathBuf>)> {
+    use serde_json::Value;
+
+    let is_yaml = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yml" | "yaml") => true,
+        Some("toml") => false,
+        _ => {
+            anyhow::bail!(
+                "Unsupported file extension of configuration file {path:?}. Supported extensions are .yml, .yaml, and .toml."
+            );
+        }
+    };
+
+    if !path.is_file() {
+        anyhow::bail!("Configuration file {path:?} not found");
+    }
+
+    let canonical = fs::canonicalize(path).with_context(|| format!("Failed to read {path:?}"))?;
+    if chain.contains(&canonical) {
+        let cycle: Vec<String> = chain
+            .iter()
+            .chain([&canonical])
+            .map(|path| format!("{path:?}"))
+            .collect();
+        anyhow::bail!(
+            "Configuration files extend each other in a cycle: {}",
+            cycle.join(" -> ")
+        );
+    }
+
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    let mut value = if is_yaml {
+        serde_yaml::from_str::<Value>(&content)
+            .with_context(|| format!("Failed to parse YAML in {path:?}"))?
+    } else {
+        toml::from_str::<Value>(&content)
+            .with_context(|| format!("Failed to parse TOML in {path:?}"))?
+    };
+    // an empty YAML file
+    if value.is_null() {
+        value = Value::Object(serde_json::Map::new());
+    }
+    let Value::Object(fields) = &mut value else {
+        anyhow::bail!("Configuration in {path:?} is not a map of keys to values");
+    };
+
+    let extends: Vec<PathBuf> = match fields.remove("extends") {
+        Some(extends) => serde_json::from_value::<OneOrMany<PathBuf>>(extends)
+            .with_context(|| format!("Invalid `extends` in {path:?}"))?
+            .into(),
+        None => Vec::new(),
+    };
+
+    let mut files =
+        vec![std::path::absolute(path).with_context(|| format!("Failed to resolve {path:?}"))?];
+
+    chain.push(canonical);
+    let mut merged = Value::Object(serde_json::Map::new());
+    for extended in extends {
+        let extended = path.parent().unwrap_or(Path::new("")).join(extended);
+        let (extended_value, extended_files) = load_value(&extended, chain)
+            .with_context(|| format!("Failed to load {extended:?}, extended by {path:?}"))?;
+        merge_values(&mut merged, extended_value);
+        files.extend(extended_files);
+    }
+    chain.pop();
+
+    merge_values(&mut merged, value);
+    Ok((merged, files))
+}
+
+/// Either a single value, or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> From<OneOrMany<T>> for Vec<T> {
+    fn from(value: OneOrMany<T>) -> Self {
+        match value {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+/// Merge `overlay` into `base`. Maps are merged key by key, while anything else in `overlay`
+/// replaces what is in `base`.
+fn merge_values(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    use serde_json::Value;
+
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+impl Conf {
+    /// Path component of `base_url`, with leading and trailing slashes.
+    pub fn root_path(&self) -> String {
+        let path = match self.base_url.split_once("://") {
+            Some((_, after_scheme)) => after_scheme
+                .find('/')
+                .map_or("", |start| &after_scheme[start..]),
+            None => &self.base_url,
+        };
+
+        let path = path.trim_matches('/');
+        if path.is_empty() {
+            "/".to_string()
+        } else {
+            format!("/{path}/")
+        }
+    }
+
+    /// The default language, followed by all other configured languages.
+    pub fn all_languages(&self) -> Vec<&str> {
+        std::iter::once(self.language.as_str())
+            .chain(
+                self.languages
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|&code| code != self.language),
+            )
+            .collect()
+    }
+
+    /// Where pages in `language` are placed in the output, relative to the root.
+    pub fn language_prefix<'a>(&self, language: &'a str) -> &'a str {
+        if language == self.language {
+            ""
+        } else {
+            language
+        }
+    }
+
+    pub fn language_name<'a>(&'a self, language: &'a str) -> &'a str {
+        self.languages
+            .get(language)
+            .and_then(|lang| lang.name.as_deref())
+            .unwrap_or(language)
+    }
+
+    /// Configuration for rendering pages in `language`.
+    pub fn for_language(&self, language: &str) -> Conf {
+        let mut conf = self.clone();
+        conf.language = language.to_string();
+        if let Some(lang) = self.languages.get(language) {
+            if lang.title.is_some() {
+                conf.title = lang.title.clone();
+            }
+            if lang.description.is_some() {
+                conf.description = lang.description.clone();
+            }
+        }
+        conf
+    }
+}
+
 pub type PartialConf = <Conf as Config>::Partial;
@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+
+use crate::config::Conf;
+
+/// Files which, in addition to `exclude`, list patterns of files to leave out of the site. Read
+/// from both `docs_dir` and the current directory, which is that of the configuration file.
+pub const IGNORE_FILES: &[&str] = &[".gitignore", ".picodocsignore"];
+
+/// Decides which files under `docs_dir` are left out of the site.
+pub struct Exclusions {
+    /// Patterns from `exclude` and ignore files in `docs_dir`
+    docs: Gitignore,
+    /// Patterns from ignore files in the current directory, along with where `docs_dir` is relative
+    /// to it
+    project: Option<(Gitignore, PathBuf)>,
+    include_dotfiles: bool,
+}
+
+impl Exclusions {
+    pub fn new(config: &Conf) -> Result<Self> {
+        let mut docs = GitignoreBuilder::new(&config.docs_dir);
+        for ignore_file in IGNORE_FILES {
+            add_ignore_file(&mut docs, &config.docs_dir.join(ignore_file))?;
+        }
+        for pattern in &config.exclude {
+            docs.add_line(None, pattern)
+                .with_context(|| format!("Invalid exclude pattern {pattern:?}"))?;
+        }
+        if let Some(nav_source) = &config.nav_source {
+            let pattern = format!("/{}", nav_source.display());
+            docs.add_line(None, &pattern)
+                .with_context(|| format!("Invalid nav_source {nav_source:?}"))?;
+        }
+
+        let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+        let docs_dir_in_project = if config.docs_dir.is_relative() {
+            Some(config.docs_dir.clone())
+        } else {
+            config
+                .docs_dir
+                .strip_prefix(&current_dir)
+                .ok()
+                .map(Path::to_path_buf)
+        };
+
+        let project = match docs_dir_in_project {
+            Some(docs_dir) => {
+                let mut project = GitignoreBuilder::new(&current_dir);
+                for ignore_file in IGNORE_FILES {
+                    add_ignore_file(&mut project, &current_dir.join(ignore_file))?;
+                }
+                Some((project.build()?, docs_dir))
+            }
+            None => None,
+        };
+
+        Ok(Exclusions {
+            docs: docs.build()?,
+            project,
+            include_dotfiles: config.include_dotfiles,
+        })
+    }
+
+    /// Whether the file or directory at `rel` (relative to `docs_dir`) is left out of the site.
+    pub fn is_excluded(&self, rel: &Path, is_dir: bool) -> bool {
+        if rel.as_os_str().is_empty() {
+            return false;
+        }
+
+        if !self.include_dotfiles
+            && rel
+                .components()
+                .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+        {
+            return true;
+        }
+
+        if self
+            .docs
+            .matched_path_or_any_parents(rel, is_dir)
+            .is_ignore()
+        {
+            return true;
+        }
+
+        self.project.as_ref().is_some_and(|(project, docs_dir)| {
+            project
+                .matched_path_or_any_parents(docs_dir.join(rel), is_dir)
+                .is_ignore()
+        })
+    }
+}
+
+fn add_ignore_file(builder: &mut GitignoreBuilder, path: &Path) -> Result<()> {
+    if !path.is_file() {
+        return Ok(());
+    }
+    match builder.add(path) {
+        Some(e) => Err(e).with_context(|| format!("Failed to read ignore file {path:?}")),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,372 @@
+use anyhow::{Context, Result};
+use clap::CommandFactory;
+use std::path::{Path, PathBuf};
+
+use crate::{args::Args, reference};
+
+const DIRECTIVE_START: &str = "{{#";
+const DIRECTIVE_END: &str = "}}";
+const SNIPPET_MARKER: &str = "--8<--";
+/// Names of the directives written as `{{#name ...}}`
+const DIRECTIVES: &[&str] = &["include", "cli", "config"];
+
+/// Expand include directives in the markdown file `source`.
+///
+/// Two syntaxes are supported:
+/// - `{{#include path}}`, anywhere in a line (mdBook style)
+/// - `--8<-- "path"`, on a line of its own (pymdownx snippets style)
+///
+/// `path` is relative to the directory of the configuration file, which is the current directory,
+/// and may be followed by `:N`, `:N:M` (line ranges, either end may be omitted) or `:name` (the
+/// lines between `ANCHOR: name` and `ANCHOR_END: name`). Included markdown files are expanded
+/// recursively.
+///
+/// `{{#cli}}` or `{{#cli subcommand...}}` is replaced by the generated reference of picodocs'
+/// command-line interface, and `{{#config}}` by the reference of all configuration keys.
+///
+/// Every file that was included is pushed to `included`. `first_line` is the line of `source` that
+/// `md` starts at, used to point out where errors are.
+pub fn expand(
+    md: &str,
+    source: &Path,
+    first_line: usize,
+    included: &mut Vec<PathBuf>,
+) -> Result<Expanded> {
+    let mut stack = vec![canonical(source)];
+    let mut source_lines = Vec::new();
+    let markdown = expand_inner(
+        md,
+        Some(first_line),
+        &mut stack,
+        included,
+        Some(&mut source_lines),
+    )?;
+    Ok(Expanded {
+        markdown,
+        source_lines,
+    })
+}
+
+/// Markdown with all directives expanded, which knows where each of its lines came from.
+pub struct Expanded {
+    pub markdown: String,
+    /// Line of the source that each line of `markdown` comes from. Lines of included files count
+    /// as the line of the directive including them.
+    source_lines: Vec<usize>,
+}
+
+impl Expanded {
+    /// The line of the source that line `index` (counting from 0) of the expanded markdown comes
+    /// from.
+    pub fn source_line(&self, index: usize) -> Option<usize> {
+        self.source_lines.get(index).copied()
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// If `source_lines` is given, the line of `md` that each line of the result comes from is pushed to
+/// it.
+fn expand_inner(
+    md: &str,
+    first_line: Option<usize>,
+    stack: &mut Vec<PathBuf>,
+    included: &mut Vec<PathBuf>,
+    mut source_lines: Option<&mut Vec<usize>>,
+) -> Result<String> {
+    let first_line_number = first_line.unwrap_or(1);
+
+    if !md.contains(DIRECTIVE_START) && !md.contains(SNIPPET_MARKER) {
+        if let Some(source_lines) = source_lines {
+            let line_count = md.split_inclusive('\n').count();
+            source_lines.extend(first_line_number..first_line_number + line_count);
+        }
+        return Ok(md.to_string());
+    }
+
+    let mut out = String::with_capacity(md.len());
+
+    for (line_index, line) in md.split_inclusive('\n').enumerate() {
+        let (content, newline) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+        let trimmed = content.trim_start();
+        let indent = &content[..content.len() - trimmed.len()];
+        let expanded_from = out.len();
+
+        let expanded = if let Some(escaped) = trimmed
+            .strip_prefix(';')
+            .filter(|t| t.starts_with(SNIPPET_MARKER))
+        {
+            out.push_str(indent);
+            out.push_str(escaped);
+            Ok(())
+        } else if let Some(spec) = trimmed.strip_prefix(SNIPPET_MARKER) {
+            let spec = spec.trim().trim_matches(|c| c == '"' || c == '\'');
+            include(spec, stack, included).map(|snippet| {
+                for (i, snippet_line) in snippet.lines().enumerate() {
+                    if i > 0 {
+                        out.push('\n');
+                    }
+                    out.push_str(indent);
+                    out.push_str(snippet_line);
+                }
+            })
+        } else {
+            expand_line(content, stack, included, &mut out)
+        };
+
+        match (expanded, first_line) {
+            (Err(e), Some(first_line)) => {
+                return Err(e.context(format!("On line {}", first_line + line_index)));
+            }
+            (expanded, _) => expanded?,
+        }
+
+        out.push_str(newline);
+
+        if let Some(source_lines) = source_lines.as_deref_mut() {
+            let line_count = out[expanded_from..].matches('\n').count().max(1);
+            source_lines.extend(std::iter::repeat_n(
+                first_line_number + line_index,
+                line_count,
+            ));
+        }
+    }
+
+    Ok(out)
+}
+
+fn expand_line(
+    mut line: &str,
+    stack: &mut Vec<PathBuf>,
+    included: &mut Vec<PathBuf>,
+    out: &mut String,
+) -> Result<()> {
+    while let Some(start) = line.find(DIRECTIVE_START) {
+        let after_start = start + DIRECTIVE_START.len();
+
+        if line[..start].ends_with('\\') {
+            out.push_str(&line[..start - 1]);
+            out.push_str(DIRECTIVE_START);
+            line = &line[after_start..];
+            continue;
+        }
+
+        let rest = &line[after_start..];
+        let Some(end) = rest.find(DIRECTIVE_END) else {
+            let name = rest.split_whitespace().next().unwrap_or("");
+            if DIRECTIVES.contains(&name) {
+                anyhow::bail!("Unterminated {DIRECTIVE_START}{name} directive: {line:?}");
+            }
+            break;
+        };
+        let directive = rest[..end].trim();
+        let (name, args) = directive
+            .split_once(char::is_whitespace)
+            .unwrap_or((directive, ""));
+
+        let replacement = match name {
+            "include" => include(args.trim(), stack, included)?,
+            "cli" => {
+                let subcommand: Vec<&str> = args.split_whitespace().collect();
+                reference::cli_markdown(&Args::command(), &subcommand, 2)?
+            }
+            "config" => reference::config_markdown(2),
+            _ => {
+                out.push_str(&line[..after_start]);
+                line = rest;
+                continue;
+            }
+        };
+
+        out.push_str(&line[..start]);
+        out.push_str(&replacement);
+        line = &rest[end + DIRECTIVE_END.len()..];
+    }
+    out.push_str(line);
+
+    Ok(())
+}
+
+/// Read the file referred to by `spec` and select the requested lines.
+fn include(spec: &str, stack: &mut Vec<PathBuf>, included: &mut Vec<PathBuf>) -> Result<String> {
+    let (path, selector) = match spec.split_once(':') {
+        Some((path, selector)) => (PathBuf::from(path), Some(selector)),
+        None => (PathBuf::from(spec), None),
+    };
+
+    let canonical_path = canonical(&path);
+    if let Some(cycle_start) = stack.iter().position(|p| p == &canonical_path) {
+        let cycle: Vec<_> = stack[cycle_start..]
+            .iter()
+            .chain(std::iter::once(&canonical_path))
+            .map(|p| p.to_string_lossy())
+            .collect();
+        anyhow::bail!("Include cycle detected: {}", cycle.join(" -> "));
+    }
+
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read included file {path:?}"))?;
+    included.push(path.clone());
+
+    let selected =
+        select_lines(&content, selector).with_context(|| format!("Failed to include {spec:?}"))?;
+
+    if path.extension().is_some_and(|ext| ext == "md") {
+        stack.push(canonical_path);
+        // line numbers are only known when including the whole file
+        let first_line = selector.is_none().then_some(1);
+        let expanded = expand_inner(&selected, first_line, stack, included, None);
+        stack.pop();
+        expanded
+    } else {
+        Ok(selected)
+    }
+}
+
+fn anchor_name<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let (_, rest) = line.split_once(marker)?;
+    rest.split_whitespace().next()
+}
+
+fn is_anchor_line(line: &str) -> bool {
+    line.contains("ANCHOR:") || line.contains("ANCHOR_END:")
+}
+
+fn select_lines(content: &str, selector: Option<&str>) -> Result<String> {
+    let lines: Vec<&str> = content.lines().collect();
+
+    let selected: Vec<&str> = match selector.map(str::trim) {
+        None | Some("") => lines,
+        Some(selector) if selector.chars().all(|c| c.is_ascii_digit() || c == ':') => {
+            let parse = |n: &str, default: usize| -> Result<usize> {
+                if n.is_empty() {
+                    Ok(default)
+                } else {
+                    n.parse()
+                        .with_context(|| format!("Invalid line number {n:?}"))
+                }
+            };
+            let (start, end) = match selector.split_once(':') {
+                Some((start, end)) => (parse(start, 1)?, parse(end, lines.len())?),
+                None => {
+                    let line = parse(selector, 1)?;
+                    (line, line)
+                }
+            };
+            lines
+                .into_iter()
+                .skip(start.saturating_sub(1))
+                .take((end + 1).saturating_sub(start.max(1)))
+                .collect()
+        }
+        Some(anchor) => {
+            let start = lines
+                .iter()
+                .position(|line| anchor_name(line, "ANCHOR:") == Some(anchor))
+                .with_context(|| format!("Anchor {anchor:?} not found"))?;
+            let end = lines[start..]
+                .iter()
+                .position(|line| anchor_name(line, "ANCHOR_END:") == Some(anchor))
+                .map(|offset| start + offset)
+                .with_context(|| format!("ANCHOR_END for {anchor:?} not found"))?;
+            lines[start + 1..end].to_vec()
+        }
+    };
+
+    Ok(selected
+        .into_iter()
+        .filter(|line| !is_anchor_line(line))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "one\n// ANCHOR: greet\ntwo\n// ANCHOR: inner\nthree\n// ANCHOR_END: inner\n// ANCHOR_END: greet\nfour";
+
+    fn select(selector: Option<&str>) -> String {
+        select_lines(CONTENT, selector).unwrap()
+    }
+
+    #[test]
+    fn selects_whole_file_without_anchor_lines() {
+        assert_eq!(select(None), "one\ntwo\nthree\nfour");
+        assert_eq!(select(Some("")), "one\ntwo\nthree\nfour");
+    }
+
+    #[test]
+    fn selects_line_ranges() {
+        assert_eq!(select(Some("1")), "one");
+        assert_eq!(select(Some("1:3")), "one\ntwo");
+        assert_eq!(select(Some(":1")), "one");
+        assert_eq!(select(Some("7:")), "four");
+        assert_eq!(select(Some("9")), "");
+    }
+
+    #[test]
+    fn selects_anchors() {
+        assert_eq!(select(Some("greet")), "two\nthree");
+        assert_eq!(select(Some("inner")), "three");
+        assert_eq!(select(Some(" inner ")), "three");
+    }
+
+    #[test]
+    fn rejects_bad_selectors() {
+        assert!(select_lines(CONTENT, Some("1:2:3")).is_err());
+        assert!(select_lines(CONTENT, Some("missing")).is_err());
+        assert!(select_lines("// ANCHOR: open\ntext", Some("open")).is_err());
+    }
+
+    #[test]
+    fn reads_anchor_names() {
+        assert_eq!(anchor_name("// ANCHOR: greet", "ANCHOR:"), Some("greet"));
+        assert_eq!(anchor_name("<!-- ANCHOR: a -->", "ANCHOR:"), Some("a"));
+        assert_eq!(anchor_name("// ANCHOR:", "ANCHOR:"), None);
+        assert_eq!(anchor_name("// ANCHOR_END: greet", "ANCHOR:"), None);
+    }
+
+    fn expand_text(line: &str) -> Result<String> {
+        let mut out = String::new();
+        expand_line(line, &mut Vec::new(), &mut Vec::new(), &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn keeps_escaped_and_unknown_directives() {
+        assert_eq!(
+            expand_text(r"Write \{{#include file.md}} to include").unwrap(),
+            "Write {{#include file.md}} to include"
+        );
+        assert_eq!(expand_text("{{#title Page}}").unwrap(), "{{#title Page}}");
+        assert_eq!(expand_text("{{#unknown").unwrap(), "{{#unknown");
+        assert_eq!(expand_text("plain text").unwrap(), "plain text");
+    }
+
+    #[test]
+    fn rejects_unterminated_directives() {
+        assert!(expand_text("{{#include file.md").is_err());
+        assert!(expand_text("{{#cli build").is_err());
+    }
+
+    #[test]
+    fn keeps_escaped_snippet_markers() {
+        let mut lines = Vec::new();
+        let out = expand_inner(
+            "text\n  ;--8<-- \"file.md\"\n",
+            Some(1),
+            &mut Vec::new(),
+            &mut Vec::new(),
+            Some(&mut lines),
+        )
+        .unwrap();
+        assert_eq!(out, "text\n  --8<-- \"file.md\"\n");
+        assert_eq!(lines, [1, 2]);
+    }
+}
@@ -1,41 +1,113 @@
 mod args;
 mod assets;
+mod cache;
 mod commands;
 mod config;
+mod exclude;
+mod includes;
+mod reference;
+mod search;
+mod shortcodes;
+mod summary;
 
+use anyhow::Context;
 use clap::{CommandFactory, Parser, crate_name};
 use clap_complete::generate;
-use confique::Partial;
-use confique::{File, FileFormat};
 
-use crate::{
-    args::{Args, Command},
-    config::PartialConf,
-};
+use crate::args::{Args, Command, ConfigAction, ImportSource};
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let partial_conf = File::with_format(
-        &args.config_path,
-        match args.config_path.extension().unwrap().to_str().unwrap() {
-            "yml" | "yaml" => FileFormat::Yaml,
-            "toml" => FileFormat::Toml,
-            _ => {
-                anyhow::bail!(
-                    "Unsupported file extension : {:?}. Supported extensions are .yml, .yaml, and .toml.",
-                    &args.config_path
-                );
-            }
-        },
-    )
-    .load::<PartialConf>()?
-    .with_fallback(PartialConf::default_values());
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .context("Failed to set up thread pool")?;
+    }
+
+    // only commands working on a site need its configuration, and they run in its directory
+    let config_path = || -> anyhow::Result<Option<std::path::PathBuf>> {
+        let Some(config_path) = args.config_path.clone().or_else(config::discover) else {
+            eprintln!("No configuration file found, using the defaults");
+            return Ok(None);
+        };
+        config::enter_project_dir(&config_path).map(Some)
+    };
 
     match args.command {
-        Command::Build { output_dir } => commands::build::run(partial_conf, output_dir)?,
-        Command::Serve { address, open } => commands::serve::run(partial_conf, address, open)?,
+        Command::Build { output_dir, drafts } => {
+            // relative to where picodocs is run, rather than to the configuration file
+            let output_dir = output_dir
+                .map(std::path::absolute)
+                .transpose()
+                .context("Failed to resolve output directory")?;
+            let config_path = config_path()?;
+            commands::build::run(
+                config::load(config_path.as_deref(), &args.overrides)?,
+                config_path.as_deref(),
+                output_dir,
+                drafts,
+            )?
+        }
+        Command::Serve {
+            address,
+            open,
+            next_free_port,
+            static_output: true,
+        } => commands::preview::run(
+            config::load(config_path()?.as_deref(), &args.overrides)?,
+            address,
+            open,
+            next_free_port,
+        )?,
+        Command::Serve {
+            address,
+            open,
+            next_free_port,
+            static_output: false,
+        } => {
+            let config_path = config_path()?;
+            commands::serve::run(
+                config::load(config_path.as_deref(), &args.overrides)?,
+                config_path,
+                args.overrides,
+                address,
+                open,
+                next_free_port,
+            )?
+        }
+        Command::Init {
+            dir,
+            format,
+            title,
+            templates,
+            no_workflow,
+            yes,
+            force,
+        } => commands::init::run(dir, format, title, templates, no_workflow, yes, force)?,
+        Command::Config {
+            action: ConfigAction::Show,
+        } => commands::config::show(config_path()?.as_deref(), &args.overrides)?,
+        Command::Import {
+            source:
+                ImportSource::Mkdocs {
+                    mkdocs_config,
+                    output_path,
+                    force,
+                },
+        } => commands::import::mkdocs(mkdocs_config, output_path, force)?,
+        Command::Import {
+            source:
+                ImportSource::Mdbook {
+                    book_config,
+                    output_path,
+                    nav_source,
+                    force,
+                },
+        } => commands::import::mdbook(book_config, output_path, nav_source, force)?,
         Command::Defaults { output_path, force } => commands::defaults::run(output_path, force)?,
+        Command::Reference { kind } => commands::reference::run(kind)?,
         Command::Completion { shell } => {
             let mut cmd = Args::command();
             generate(shell, &mut cmd, crate_name!(), &mut std::io::stdout());
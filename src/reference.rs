@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use clap::{Arg, Command};
+use confique::{Config, meta};
+use serde_json::Value;
+use std::fmt::Write;
+
+use crate::config::Conf;
+
+/// Generate a markdown reference for `cmd` and all of its subcommands, with headings starting at
+/// `level`.
+///
+/// If `subcommand` is non-empty, only the reference for that (nested) subcommand is generated.
+pub fn cli_markdown(cmd: &Command, subcommand: &[&str], level: usize) -> Result<String> {
+    let mut cmd = cmd.clone();
+    cmd.build();
+
+    let mut target = &mut cmd;
+    for name in subcommand {
+        target = target
+            .find_subcommand_mut(name)
+            .with_context(|| format!("No such subcommand: {}", subcommand.join(" ")))?;
+    }
+
+    let mut md = String::new();
+    write_command(&mut md, target, level);
+    Ok(md)
+}
+
+fn heading(level: usize) -> String {
+    "#".repeat(level.clamp(1, 6))
+}
+
+fn write_command(md: &mut String, cmd: &mut Command, level: usize) {
+    let name = cmd
+        .get_bin_name()
+        .unwrap_or_else(|| cmd.get_name())
+        .to_string();
+
+    let _ = writeln!(md, "{} `{name}`\n", heading(level));
+
+    if let Some(about) = cmd.get_long_about().or_else(|| cmd.get_about()) {
+        let _ = writeln!(md, "{about}\n");
+    }
+
+    let usage = cmd.render_usage().to_string();
+    let usage = usage.strip_prefix("Usage: ").unwrap_or(&usage);
+    let _ = writeln!(md, "```text\n{usage}\n```\n");
+
+    let (positionals, options): (Vec<&Arg>, Vec<&Arg>) = cmd
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .partition(|arg| arg.is_positional());
+
+    for (title, args) in [("Arguments", positionals), ("Options", options)] {
+        if args.is_empty() {
+            continue;
+        }
+        let _ = writeln!(md, "**{title}:**\n");
+        for arg in args {
+            let _ = writeln!(md, "- {}", describe_arg(arg));
+        }
+        md.push('\n');
+    }
+
+    let subcommands: Vec<&Command> = cmd
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+        .collect();
+    if !subcommands.is_empty() {
+        let _ = writeln!(md, "**Commands:**\n");
+        for sub in subcommands {
+            let _ = write!(md, "- `{}`", sub.get_name());
+            if let Some(about) = sub.get_about() {
+                let _ = write!(md, ": {about}");
+            }
+            md.push('\n');
+        }
+        md.push('\n');
+    }
+
+    for sub in cmd.get_subcommands_mut() {
+        if sub.is_hide_set() || sub.get_name() == "help" {
+            continue;
+        }
+        write_command(md, sub, level + 1);
+    }
+}
+
+fn value_name(arg: &Arg) -> String {
+    match arg.get_value_names() {
+        Some(names) => names
+            .iter()
+            .map(|name| format!("<{name}>"))
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+    }
+}
+
+fn describe_arg(arg: &Arg) -> String {
+    let takes_values = arg.get_action().takes_values();
+
+    let signature = if arg.is_positional() {
+        format!("`{}`", value_name(arg))
+    } else {
+        let mut names = Vec::new();
+        if let Some(short) = arg.get_short() {
+            names.push(format!("-{short}"));
+        }
+        if let Some(long) = arg.get_long() {
+            names.push(format!("--{long}"));
+        }
+        let mut signature = names
+            .iter()
+            .map(|name| format!("`{name}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if takes_values {
+            signature.insert_str(signature.len() - 1, &format!(" {}", value_name(arg)));
+        }
+        signature
+    };
+
+    let mut description = signature;
+    if let Some(help) = arg.get_long_help().or_else(|| arg.get_help()) {
+        let _ = write!(description, ": {help}");
+    }
+
+    if takes_values {
+        let defaults: Vec<_> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| format!("`{}`", value.to_string_lossy()))
+            .collect();
+        if !defaults.is_empty() {
+            let _ = write!(description, " (default: {})", defaults.join(", "));
+        }
+
+        let possible_values: Vec<_> = arg
+            .get_possible_values()
+            .iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| format!("`{}`", value.get_name()))
+            .collect();
+        if !possible_values.is_empty() {
+            let _ = write!(
+                description,
+                ". Possible values: {}",
+                possible_values.join(", ")
+            );
+        }
+    }
+
+    description
+}
+
+/// JSON Schema of the configuration file, with defaults taken from `Conf`.
+pub fn config_json_schema() -> Value {
+    let mut schema = schemars::schema_for!(Conf);
+    schema.insert("title".to_string(), "picodocs configuration".into());
+    // every non-optional field has a default
+    schema.remove("required");
+
+    if let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) {
+        for field in Conf::META.fields {
+            if let (Some(property), Some(default)) =
+                (properties.get_mut(field.name), field_default(field))
+            {
+                property["default"] = default;
+            }
+        }
+    }
+
+    schema.to_value()
+}
+
+fn field_default(field: &meta::Field) -> Option<Value> {
+    match field.kind {
+        meta::FieldKind::Leaf {
+            kind: meta::LeafKind::Required {
+                default: Some(default),
+            },
+            ..
+        } => serde_json::to_value(default).ok(),
+        _ => None,
+    }
+}
+
+/// Human readable type of a property in a JSON Schema.
+fn schema_type(property: &Value) -> String {
+    if let Some(reference) = property.get("$ref").and_then(Value::as_str) {
+        return reference
+            .rsplit('/')
+            .next()
+            .unwrap_or(reference)
+            .to_string();
+    }
+
+    let types: Vec<&str> = match property.get("type") {
+        Some(Value::String(ty)) => vec![ty],
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .filter(|ty| *ty != "null")
+            .collect(),
+        _ => return "any".to_string(),
+    };
+
+    types
+        .into_iter()
+        .map(|ty| match (ty, property.get("items")) {
+            ("array", Some(items)) => format!("array of {}", schema_type(items)),
+            _ => ty.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Generate a markdown reference of all configuration keys, with headings at `level`.
+pub fn config_markdown(level: usize) -> String {
+    let schema = config_json_schema();
+    let mut md = String::new();
+
+    for field in Conf::META.fields {
+        let _ = writeln!(md, "{} `{}`\n", heading(level), field.name);
+
+        let doc: Vec<&str> = field.doc.iter().map(|line| line.trim()).collect();
+        if !doc.is_empty() {
+            let _ = writeln!(md, "{}\n", doc.join(" "));
+        }
+
+        let property = &schema["properties"][field.name];
+        let _ = writeln!(md, "- Type: `{}`", schema_type(property));
+        match field_default(field) {
+            Some(default) => {
+                let _ = writeln!(md, "- Default: `{default}`");
+            }
+            None => {
+                let _ = writeln!(md, "- Optional");
+            }
+        }
+        if let meta::FieldKind::Leaf { env: Some(env), .. } = field.kind {
+            let _ = writeln!(md, "- Environment variable: `{env}`");
+        }
+        md.push('\n');
+    }
+
+    md
+}
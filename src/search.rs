@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+/// Where the search index is placed in the output
+pub const INDEX_PATH: &str = "search-index.json";
+
+/// Where the script querying the search index is placed in the output
+pub const SCRIPT_PATH: &str = "search.js";
+
+pub static SCRIPT: &[u8] = include_bytes!("../templates/search.js");
+
+/// A heading within a page, which search results can link to directly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heading {
+    #[serde(rename = "a")]
+    pub anchor: String,
+    #[serde(rename = "t")]
+    pub text: String,
+}
+
+/// A page in the search index.
+///
+/// Keys are kept short, since the whole index is downloaded by the browser on first search.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchEntry {
+    #[serde(rename = "u")]
+    pub url_path: String,
+    #[serde(rename = "t")]
+    pub title: String,
+    #[serde(rename = "k", skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    #[serde(rename = "h", skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<Heading>,
+    #[serde(rename = "b")]
+    pub text: String,
+}
+
+/// Whether the page at `url_path` is excluded from the index by any of the patterns in
+/// `search_exclude`. A pattern excludes the page with that path, and all pages below it. The
+/// top-level `index.md` only excludes the home page.
+pub fn is_excluded(url_path: &str, search_exclude: &[String]) -> bool {
+    search_exclude.iter().any(|pattern| {
+        let pattern = pattern.trim_matches('/');
+        let pattern = pattern.strip_suffix(".md").unwrap_or(pattern);
+        if pattern == "index" {
+            return url_path.is_empty();
+        }
+        let pattern = pattern.strip_suffix("/index").unwrap_or(pattern);
+
+        pattern.is_empty()
+            || url_path == pattern
+            || url_path
+                .strip_prefix(pattern)
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Collapse all runs of whitespace into single spaces.
+pub fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excluded(url_path: &str, pattern: &str) -> bool {
+        is_excluded(url_path, &[pattern.to_string()])
+    }
+
+    #[test]
+    fn excludes_page_and_pages_below() {
+        assert!(excluded("changelog", "changelog.md"));
+        assert!(excluded("reference", "reference"));
+        assert!(excluded("reference/cli", "reference"));
+        assert!(!excluded("references", "reference"));
+        assert!(!excluded("guide", "reference"));
+    }
+
+    #[test]
+    fn top_level_index_excludes_home_page_only() {
+        assert!(excluded("", "index.md"));
+        assert!(!excluded("guide", "index.md"));
+    }
+
+    #[test]
+    fn nested_index_excludes_its_directory() {
+        assert!(excluded("guide", "guide/index.md"));
+        assert!(excluded("guide/setup", "guide/index.md"));
+        assert!(!excluded("", "guide/index.md"));
+        assert!(!excluded("guides", "guide/index.md"));
+    }
+
+    #[test]
+    fn index_is_only_stripped_as_a_whole_segment() {
+        assert!(excluded("reindex", "reindex.md"));
+        assert!(!excluded("re", "reindex.md"));
+        assert!(!excluded("", "reindex.md"));
+        assert!(!excluded("guide", "guide/reindex.md"));
+    }
+
+    #[test]
+    fn trailing_slash_is_ignored() {
+        assert!(excluded("guide", "guide/"));
+        assert!(excluded("guide/setup", "/guide/"));
+        assert!(!excluded("guides", "guide/"));
+    }
+}
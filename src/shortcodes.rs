@@ -0,0 +1,318 @@
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use pulldown_cmark::{Event, Parser, Tag};
+use std::ops::Range;
+use std::sync::RwLock;
+use tera::Tera;
+
+/// Shortcodes that are always available. A template with the same name in `shortcodes/` takes
+/// precedence.
+const BUILTIN_SHORTCODES: &[(&str, &str)] = &[
+    (
+        "youtube.html",
+        r#"<div class="video"><iframe src="https://www.youtube-nocookie.com/embed/{{ id }}{% if autoplay %}?autoplay=1{% endif %}" title="{{ title | default(value="YouTube video") }}" allow="fullscreen; picture-in-picture"></iframe></div>"#,
+    ),
+    (
+        "note.html",
+        r#"{% set kind = kind | default(value="note") %}<div class="admonition {{ kind }}"><p class="admonition-title">{{ title | default(value=kind | capitalize) }}</p>{{ body | safe }}</div>"#,
+    ),
+    (
+        "details.html",
+        r#"<details{% if open %} open{% endif %}><summary>{{ summary | default(value="Details") }}</summary>{{ body | safe }}</details>"#,
+    ),
+];
+
+/// Templates of all shortcodes, loaded on first use
+static SHORTCODES: OnceCell<RwLock<Tera>> = OnceCell::new();
+
+fn shortcodes() -> Result<&'static RwLock<Tera>> {
+    SHORTCODES.get_or_try_init(|| load().map(RwLock::new))
+}
+
+fn load() -> Result<Tera> {
+    let mut tera = Tera::new("shortcodes/**/*.html").context("Failed to load shortcodes")?;
+    let mut builtins = Tera::default();
+    builtins
+        .add_raw_templates(BUILTIN_SHORTCODES.iter().copied())
+        .context("Failed to load built-in shortcodes")?;
+    tera.extend(&builtins)
+        .context("Failed to merge built-in shortcodes")?;
+    Ok(tera)
+}
+
+/// Load the templates in `shortcodes/` again, after they have been edited.
+pub fn reload() -> Result<()> {
+    let tera = load()?;
+    match SHORTCODES.get() {
+        Some(shortcodes) => *shortcodes.write().unwrap() = tera,
+        None => {
+            // a concurrent first use loaded them just now, which is as recent
+            let _ = SHORTCODES.set(RwLock::new(tera));
+        }
+    }
+    Ok(())
+}
+
+/// Markdown where every shortcode has been replaced by a placeholder, along with the rendered
+/// output of each shortcode.
+///
+/// Rendered HTML can't be inserted into the markdown directly, since the markdown renderer would
+/// wrap or escape it. Call [`Expanded::restore`] on the final HTML instead.
+pub struct Expanded {
+    pub markdown: String,
+    outputs: Vec<String>,
+}
+
+impl Expanded {
+    pub fn restore(&self, mut html: String) -> String {
+        for (i, output) in self.outputs.iter().enumerate() {
+            let placeholder = placeholder(i);
+            // block shortcodes end up as their own paragraph
+            html = html
+                .replace(&format!("<p>{placeholder}</p>"), output)
+                .replace(&placeholder, output);
+        }
+        html
+    }
+}
+
+const PLACEHOLDER_PREFIX: &str = "@@picodocs-shortcode-";
+const PLACEHOLDER_SUFFIX: &str = "@@";
+
+fn placeholder(i: usize) -> String {
+    format!("{PLACEHOLDER_PREFIX}{i}{PLACEHOLDER_SUFFIX}")
+}
+
+/// Remove shortcode placeholders from text, for when the rendered output is not wanted.
+pub fn strip_placeholders(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(PLACEHOLDER_PREFIX) {
+        stripped.push_str(&rest[..start]);
+        let after_prefix = &rest[start + PLACEHOLDER_PREFIX.len()..];
+        rest = match after_prefix.find(PLACEHOLDER_SUFFIX) {
+            Some(end) => &after_prefix[end + PLACEHOLDER_SUFFIX.len()..],
+            None => after_prefix,
+        };
+    }
+    stripped.push_str(rest);
+
+    stripped
+}
+
+/// A parsed `name(key=value, ...)` invocation.
+struct Call {
+    name: String,
+    args: tera::Context,
+}
+
+impl Call {
+    /// Returns `None` if `s` does not look like a shortcode at all, and an error if it does but
+    /// its arguments are malformed.
+    fn parse(s: &str) -> Option<Result<Self>> {
+        let s = s.trim();
+        let open = s.find('(')?;
+        let name = &s[..open];
+        if name.is_empty()
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            || !s.ends_with(')')
+        {
+            return None;
+        }
+
+        Some(
+            parse_args(&s[open + 1..s.len() - 1])
+                .map(|args| Call {
+                    name: name.to_string(),
+                    args,
+                })
+                .with_context(|| format!("Invalid arguments to shortcode `{name}`")),
+        )
+    }
+
+    fn render(&self, body: Option<String>) -> Result<String> {
+        let template = format!("{}.html", self.name);
+        let shortcodes = shortcodes()?.read().unwrap();
+        if !shortcodes.get_template_names().any(|name| name == template) {
+            anyhow::bail!("Unknown shortcode `{}`", self.name);
+        }
+
+        let mut ctx = self.args.clone();
+        if let Some(body) = body {
+            ctx.insert("body", &body);
+        }
+
+        shortcodes
+            .render(&template, &ctx)
+            .with_context(|| format!("Render shortcode `{}`", self.name))
+    }
+}
+
+fn parse_args(mut s: &str) -> Result<tera::Context> {
+    let mut args = tera::Context::new();
+
+    loop {
+        s = s.trim_start();
+        if s.is_empty() {
+            return Ok(args);
+        }
+
+        let (key, rest) = s.split_once('=').context("expected `key=value`")?;
+        let key = key.trim();
+        let rest = rest.trim_start();
+
+        let (value, rest) = match rest.chars().next() {
+            Some(quote @ ('"' | '\'' | '`')) => {
+                let end = rest[1..]
+                    .find(quote)
+                    .with_context(|| format!("unterminated string for `{key}`"))?;
+                (tera::Value::from(&rest[1..end + 1]), &rest[end + 2..])
+            }
+            _ => {
+                let end = rest.find(',').unwrap_or(rest.len());
+                let literal = rest[..end].trim();
+                let value = match literal {
+                    "true" => tera::Value::from(true),
+                    "false" => tera::Value::from(false),
+                    _ => {
+                        if let Ok(int) = literal.parse::<i64>() {
+                            tera::Value::from(int)
+                        } else if let Ok(float) = literal.parse::<f64>() {
+                            tera::Value::from(float)
+                        } else {
+                            anyhow::bail!("invalid value for `{key}`: {literal:?}");
+                        }
+                    }
+                };
+                (value, &rest[end..])
+            }
+        };
+        args.insert(key, &value);
+
+        s = rest.trim_start();
+        s = match s.strip_prefix(',') {
+            Some(rest) => rest,
+            None if s.is_empty() => s,
+            None => anyhow::bail!("expected `,` after `{key}`"),
+        };
+    }
+}
+
+/// Byte ranges of inline code and code blocks, where shortcodes are left untouched.
+fn code_ranges(md: &str) -> Vec<Range<usize>> {
+    Parser::new(md)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Find the `{% end %}` closing a block shortcode whose body starts at `from`.
+///
+/// Returns the range of the closing tag.
+fn find_end(md: &str, from: usize, in_code: &dyn Fn(usize) -> bool) -> Option<Range<usize>> {
+    let mut depth = 0;
+    let mut cursor = from;
+
+    while let Some(offset) = md[cursor..].find("{%") {
+        let start = cursor + offset;
+        let close = md[start..].find("%}")?;
+        let end = start + close + 2;
+        cursor = start + 2;
+
+        if in_code(start) {
+            continue;
+        }
+
+        let inner = &md[start + 2..start + close];
+        if inner.trim() == "end" {
+            if depth == 0 {
+                return Some(start..end);
+            }
+            depth -= 1;
+        } else if Call::parse(inner).is_some() {
+            depth += 1;
+        }
+    }
+
+    None
+}
+
+/// Render all `{{ name(...) }}` and `{% name(...) %}body{% end %}` shortcodes in `md`.
+///
+/// Bodies of block shortcodes are rendered with `render_body` before being passed to the template
+/// as `body`, along with the index of the line of `md` that the body starts on.
+///
+/// `line_of` gives the line of the source file that a line of `md` comes from, by index, which is
+/// used to point out where errors are.
+pub fn expand(
+    md: &str,
+    line_of: &dyn Fn(usize) -> Option<usize>,
+    render_body: &dyn Fn(&str, usize) -> Result<String>,
+) -> Result<Expanded> {
+    let code = code_ranges(md);
+    let in_code = |pos: usize| code.iter().any(|range| range.contains(&pos));
+    let line_index = |pos: usize| md[..pos].matches('\n').count();
+    let at_line = |e: anyhow::Error, pos: usize| match line_of(line_index(pos)) {
+        Some(line) => e.context(format!("On line {line}")),
+        None => e,
+    };
+
+    let mut markdown = String::with_capacity(md.len());
+    let mut outputs = Vec::new();
+    let mut copied_until = 0;
+    let mut cursor = 0;
+
+    while let Some(offset) = md[cursor..].find('{') {
+        let start = cursor + offset;
+        cursor = start + 1;
+
+        if in_code(start) {
+            continue;
+        }
+
+        let rest = &md[start..];
+        let (output, end) = if let Some(inner) = rest.strip_prefix("{{") {
+            let Some(close) = inner.find("}}") else {
+                continue;
+            };
+            let Some(call) = Call::parse(&inner[..close]) else {
+                continue;
+            };
+            let output = call
+                .and_then(|call| call.render(None))
+                .map_err(|e| at_line(e, start))?;
+            (output, start + 2 + close + 2)
+        } else if let Some(inner) = rest.strip_prefix("{%") {
+            let Some(close) = inner.find("%}") else {
+                continue;
+            };
+            let Some(call) = Call::parse(&inner[..close]) else {
+                continue;
+            };
+            let call = call.map_err(|e| at_line(e, start))?;
+            let body_start = start + 2 + close + 2;
+            let end_tag = find_end(md, body_start, &in_code)
+                .with_context(|| format!("Shortcode `{}` is missing `{{% end %}}`", call.name))
+                .map_err(|e| at_line(e, start))?;
+            // errors in the body point out their own line
+            let body = render_body(&md[body_start..end_tag.start], line_index(body_start))?;
+            let output = call.render(Some(body)).map_err(|e| at_line(e, start))?;
+            (output, end_tag.end)
+        } else {
+            continue;
+        };
+
+        markdown.push_str(&md[copied_until..start]);
+        markdown.push_str(&placeholder(outputs.len()));
+        outputs.push(output);
+        copied_until = end;
+        cursor = end;
+    }
+    markdown.push_str(&md[copied_until..]);
+
+    Ok(Expanded { markdown, outputs })
+}
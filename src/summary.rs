@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::NavItem;
+
+/// Read the mdBook `SUMMARY.md` at `path` as navigation.
+pub fn load(path: &Path) -> Result<Vec<NavItem>> {
+    let md = fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    Ok(parse(&md))
+}
+
+/// A chapter whose list item is still being read
+#[derive(Default)]
+struct Chapter {
+    title: String,
+    path: String,
+    children: Vec<NavItem>,
+}
+
+impl Chapter {
+    /// Chapters with sub-chapters become sections, led by the chapter's own page. Draft chapters,
+    /// which have no page, are left out along with their sub-chapters.
+    fn into_nav_item(self) -> Option<NavItem> {
+        if self.path.is_empty() {
+            return None;
+        }
+        if self.children.is_empty() {
+            return Some(NavItem::ExternalLink(BTreeMap::from([(
+                self.title, self.path,
+            )])));
+        }
+        let mut items = vec![NavItem::Path(self.path)];
+        items.extend(self.children);
+        Some(NavItem::Section(BTreeMap::from([(self.title, items)])))
+    }
+}
+
+/// Parse the navigation from an mdBook `SUMMARY.md`.
+///
+/// Prefix, numbered and suffix chapters are all kept in order. Each part title becomes a section
+/// holding the chapters up to the next part title. Separators only have a visual purpose in mdBook,
+/// and are left out.
+pub fn parse(md: &str) -> Vec<NavItem> {
+    let mut nav = Vec::new();
+    // title and chapters of the part being read, which has no title before the first part title
+    let mut part: (Option<String>, Vec<NavItem>) = (None, Vec::new());
+    // whether nothing but the title of the summary may have been read yet
+    let mut at_start = true;
+
+    // chapters of the list items being read, innermost last
+    let mut chapters: Vec<Chapter> = Vec::new();
+    // a chapter outside of a list, i.e. a prefix or suffix chapter
+    let mut loose_chapter: Option<Chapter> = None;
+    let mut heading: Option<String> = None;
+    let mut in_link = false;
+
+    for event in Parser::new(md) {
+        match event {
+            Event::Start(Tag::Heading {
+                level: HeadingLevel::H1,
+                ..
+            }) => heading = Some(String::new()),
+            Event::End(TagEnd::Heading(HeadingLevel::H1)) => {
+                let title = heading.take().unwrap_or_default();
+                // the title of the summary itself, which mdBook ignores
+                if std::mem::take(&mut at_start) {
+                    continue;
+                }
+                finish_part(&mut nav, &mut part);
+                part.0 = Some(title.trim().to_string());
+            }
+            Event::Start(Tag::Item) => chapters.push(Chapter::default()),
+            Event::End(TagEnd::Item) => {
+                if let Some(item) = chapters.pop().and_then(Chapter::into_nav_item) {
+                    match chapters.last_mut() {
+                        Some(parent) => parent.children.push(item),
+                        None => part.1.push(item),
+                    }
+                }
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                in_link = true;
+                at_start = false;
+                match chapters.last_mut() {
+                    Some(chapter) => chapter.path = dest_url.to_string(),
+                    None => {
+                        loose_chapter = Some(Chapter {
+                            path: dest_url.to_string(),
+                            ..Chapter::default()
+                        });
+                    }
+                }
+            }
+            Event::End(TagEnd::Link) => {
+                in_link = false;
+                // suffix chapters don't belong to the last part
+                if let Some(item) = loose_chapter.take().and_then(Chapter::into_nav_item) {
+                    finish_part(&mut nav, &mut part);
+                    nav.push(item);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = &mut heading {
+                    heading.push_str(&text);
+                } else if in_link
+                    && let Some(chapter) = loose_chapter.as_mut().or(chapters.last_mut())
+                {
+                    chapter.title.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+    finish_part(&mut nav, &mut part);
+
+    nav
+}
+
+/// Add the chapters of `part` to `nav`, as a section if the part has a title.
+fn finish_part(nav: &mut Vec<NavItem>, part: &mut (Option<String>, Vec<NavItem>)) {
+    let (title, items) = std::mem::take(part);
+    match title {
+        Some(title) => nav.push(NavItem::Section(BTreeMap::from([(title, items)]))),
+        None => nav.extend(items),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_parses(md: &str, nav: &str) {
+        let parsed = serde_yaml::to_value(parse(md)).unwrap();
+        let expected: serde_yaml::Value = serde_yaml::from_str(nav).unwrap();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn keeps_prefix_numbered_and_suffix_chapters_in_order() {
+        assert_parses(
+            "# Summary
+
+[Introduction](intro.md)
+
+- [Setup](setup.md)
+- [Usage](usage.md)
+
+---
+
+[Contributors](contributors.md)
+",
+            "
+- Introduction: intro.md
+- Setup: setup.md
+- Usage: usage.md
+- Contributors: contributors.md
+",
+        );
+    }
+
+    #[test]
+    fn nests_sub_chapters_in_sections_led_by_their_chapter() {
+        assert_parses(
+            "# Summary
+
+- [Guide](guide/index.md)
+    - [Setup](guide/setup.md)
+        - [On Linux](guide/linux.md)
+- [`build` command](build.md)
+",
+            "
+- Guide:
+    - guide/index.md
+    - Setup:
+        - guide/setup.md
+        - On Linux: guide/linux.md
+- build command: build.md
+",
+        );
+    }
+
+    #[test]
+    fn groups_chapters_by_part() {
+        assert_parses(
+            "# Summary
+
+[Introduction](intro.md)
+
+# User guide
+
+- [Setup](setup.md)
+
+# Reference
+
+- [CLI](cli.md)
+
+[License](license.md)
+",
+            "
+- Introduction: intro.md
+- User guide:
+    - Setup: setup.md
+- Reference:
+    - CLI: cli.md
+- License: license.md
+",
+        );
+    }
+
+    #[test]
+    fn leaves_out_draft_chapters() {
+        assert_parses(
+            "- [Done](done.md)
+- [Planned]()
+    - [Also planned](planned.md)
+",
+            "
+- Done: done.md
+",
+        );
+    }
+}
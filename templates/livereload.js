@@ -0,0 +1,130 @@
+// Injected into pages by `picodocs serve`. Applies changes to the site without reloading the page
+// where possible, and shows build errors.
+(() => {
+	const SCROLL_KEY = "picodocs-scroll";
+	const OVERLAY_ID = "picodocs-build-error";
+
+	// path of a URL, in the form used by the server for its assets
+	const assetPath = (url) =>
+		decodeURIComponent(new URL(url, location.href).pathname)
+			.replace(/(^|\/)index\.html$/, "")
+			.replace(/^\/+|\/+$/g, "");
+
+	const navToggle = () => document.getElementById("nav-toggle");
+
+	const reload = () => {
+		sessionStorage.setItem(
+			SCROLL_KEY,
+			JSON.stringify({
+				path: location.pathname,
+				x: scrollX,
+				y: scrollY,
+				nav: navToggle()?.checked,
+			}),
+		);
+		location.reload();
+	};
+
+	const restoreScroll = () => {
+		const saved = JSON.parse(sessionStorage.getItem(SCROLL_KEY) || "null");
+		sessionStorage.removeItem(SCROLL_KEY);
+		if (saved?.path !== location.pathname) return;
+		scrollTo(saved.x, saved.y);
+		if (navToggle()) navToggle().checked = saved.nav;
+	};
+	if (document.readyState === "complete") restoreScroll();
+	else addEventListener("load", restoreScroll);
+
+	// replace the content and navigation of the page with those of the new version
+	const swapContent = async () => {
+		const response = await fetch(location.href, { cache: "no-store" });
+		if (!response.ok) return reload();
+		const fresh = new DOMParser().parseFromString(await response.text(), "text/html");
+
+		const main = document.querySelector("main");
+		const freshMain = fresh.querySelector("main");
+		if (!main || !freshMain) return reload();
+
+		const navOpen = navToggle()?.checked;
+		main.replaceWith(freshMain);
+		const nav = document.querySelector("nav");
+		const freshNav = fresh.querySelector("nav");
+		if (nav && freshNav) nav.replaceWith(freshNav);
+		if (navToggle()) navToggle().checked = navOpen;
+		document.title = fresh.title;
+	};
+
+	// reload stylesheets and images in place, returning whether any other resource of the page changed
+	const refreshResources = (changed, all) => {
+		let needsReload = false;
+		const stamp = Date.now();
+		for (const element of document.querySelectorAll("link[href], img[src], script[src]")) {
+			const url = element.href || element.src;
+			const path = assetPath(url);
+			if (!all && !changed.has(path)) continue;
+
+			const fresh = new URL(url);
+			fresh.searchParams.set("picodocs", stamp);
+			if (element.matches("link[rel=stylesheet]")) {
+				// swap once loaded, to avoid a flash of unstyled content
+				const link = element.cloneNode();
+				link.href = fresh.href;
+				link.onload = () => element.remove();
+				element.after(link);
+			} else if (element.matches("img")) {
+				element.src = fresh.href;
+			} else if (element.matches("script") && changed.has(path)) {
+				needsReload = true;
+			}
+		}
+		return needsReload;
+	};
+
+	const source = new EventSource("/~~~picodocs-reload");
+	source.onmessage = (e) => {
+		if (e.data === "reload") reload();
+	};
+	source.addEventListener("change", async (e) => {
+		document.getElementById(OVERLAY_ID)?.remove();
+
+		// `null` if every page changed
+		const paths = JSON.parse(e.data);
+		const all = paths === null;
+		const changed = new Set(paths || []);
+
+		if (refreshResources(changed, all)) return reload();
+		if (all || changed.has(assetPath(location.href))) {
+			try {
+				await swapContent();
+			} catch {
+				reload();
+			}
+		}
+	});
+	source.addEventListener("build-error", (e) => {
+		document.getElementById(OVERLAY_ID)?.remove();
+
+		const overlay = document.createElement("div");
+		overlay.id = OVERLAY_ID;
+		overlay.style.cssText =
+			"position: fixed; inset: 0; z-index: 2147483647; overflow: auto; padding: 2rem; background: rgb(24 24 24 / 95%); color: #ff8a80; font: 14px/1.5 monospace";
+
+		const close = document.createElement("button");
+		close.textContent = "Dismiss";
+		close.style.cssText = "float: right; font: inherit; cursor: pointer";
+		close.onclick = () => overlay.remove();
+
+		const heading = document.createElement("strong");
+		heading.textContent = "Build failed";
+
+		const message = document.createElement("pre");
+		message.style.whiteSpace = "pre-wrap";
+		message.textContent = JSON.parse(e.data);
+
+		overlay.append(close, heading, message);
+		document.body.append(overlay);
+	});
+	document.addEventListener("keydown", (e) => {
+		if (e.key === "Escape") document.getElementById(OVERLAY_ID)?.remove();
+	});
+})();
@@ -0,0 +1,105 @@
+// Client-side search over the index generated by picodocs at build time.
+// The index is only fetched once the search box is first used.
+(() => {
+	const input = document.getElementById("search");
+	const results = document.getElementById("search-results");
+	if (!input || !results) return;
+
+	input.hidden = false;
+
+	const WEIGHTS = { title: 10, keyword: 7, heading: 5, text: 1 };
+	const MAX_RESULTS = 10;
+
+	const tokenize = (text) =>
+		text
+			.toLowerCase()
+			.split(/[^\p{L}\p{N}]+/u)
+			.filter(Boolean);
+
+	let index = null;
+	const loadIndex = async () => {
+		if (index) return index;
+		const response = await fetch(input.dataset.index || "/search-index.json");
+		index = (await response.json()).map((page) => ({
+			page,
+			title: tokenize(page.t),
+			keywords: (page.k || []).flatMap(tokenize),
+			headings: (page.h || []).map((h) => ({ ...h, tokens: tokenize(h.t) })),
+			text: tokenize(page.b),
+		}));
+		return index;
+	};
+
+	// number of tokens starting with `term`
+	const prefixMatches = (tokens, term) =>
+		tokens.reduce((count, token) => count + token.startsWith(term), 0);
+
+	const score = (entry, terms) => {
+		let total = 0;
+		let bestHeading = null;
+		for (const term of terms) {
+			let termScore =
+				WEIGHTS.title * prefixMatches(entry.title, term) +
+				WEIGHTS.keyword * prefixMatches(entry.keywords, term) +
+				WEIGHTS.text * Math.min(prefixMatches(entry.text, term), 10);
+			for (const heading of entry.headings) {
+				const matches = prefixMatches(heading.tokens, term);
+				if (matches === 0) continue;
+				termScore += WEIGHTS.heading * matches;
+				bestHeading ??= heading;
+			}
+			// every term must match somewhere
+			if (termScore === 0) return null;
+			total += termScore;
+		}
+		return { total, bestHeading };
+	};
+
+	const snippet = (text, terms) => {
+		const lower = text.toLowerCase();
+		const positions = terms.map((term) => lower.indexOf(term)).filter((i) => i >= 0);
+		// terms may only match the title or headings, then show the start of the body
+		const position = positions.length > 0 ? Math.min(...positions) : 0;
+		const start = Math.max(0, position - 40);
+		const end = start + 160;
+		return (start > 0 ? "…" : "") + text.slice(start, end) + (end < text.length ? "…" : "");
+	};
+
+	const render = (matches, terms) => {
+		results.replaceChildren(
+			...matches.map(({ entry, bestHeading }) => {
+				const item = document.createElement("li");
+				const link = document.createElement("a");
+				link.href = (input.dataset.root || "/") + entry.page.u + (bestHeading ? "#" + bestHeading.a : "");
+				link.textContent = entry.page.t + (bestHeading ? " › " + bestHeading.t : "");
+				const preview = document.createElement("p");
+				preview.textContent = snippet(entry.page.b, terms);
+				item.append(link, preview);
+				return item;
+			}),
+		);
+		results.hidden = matches.length === 0;
+	};
+
+	const search = async () => {
+		const terms = tokenize(input.value);
+		if (terms.length === 0) {
+			render([], terms);
+			return;
+		}
+		const matches = (await loadIndex())
+			.map((entry) => ({ entry, ...score(entry, terms) }))
+			.filter((match) => match.total)
+			.sort((a, b) => b.total - a.total)
+			.slice(0, MAX_RESULTS);
+		render(matches, terms);
+	};
+
+	input.addEventListener("input", search);
+	input.addEventListener("keydown", (e) => {
+		if (e.key === "Escape") {
+			input.value = "";
+			render([], []);
+		}
+	});
+})();